@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use crate::errors::Error;
+use crate::input;
+
+#[derive(Clone, Copy)]
+pub enum ArchiveKind {
+    // possibly compressed with gzip, bzip2, xz or zstd
+    Tar,
+    Zip,
+}
+
+impl ArchiveKind {
+    // archives are recognized by their file name
+    pub fn from_path(path: &str) -> Option<Self> {
+        let file_name = std::path::Path::new(path).file_name()?.to_str()?.to_ascii_lowercase();
+        const TAR_SUFFIXES : &[&str] = &[
+            ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz", ".tbz2", ".tar.xz", ".txz", ".tar.zst", ".tzst",
+        ];
+        if TAR_SUFFIXES.iter().any(|suffix| file_name.ends_with(suffix)) {
+            Some(ArchiveKind::Tar)
+        } else if file_name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+// For --search-archives: calls `search` with the path and the contents of each regular file in
+// the archive, in the order they are stored. Directories, links etc. are skipped. Errors reading
+// the archive itself are reported for `path`.
+pub fn for_each_member(file: File, path: &str, kind: ArchiveKind,
+                       mut search: impl FnMut(&str, Box<dyn Read + '_>) -> Result<(), Error>) -> Result<(), Error> {
+    let archive_error = |error: io::Error| Error::io(path, error);
+    match kind {
+        ArchiveKind::Tar => {
+            let reader = input::decompressing_reader(Box::new(file)).map_err(archive_error)?;
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries().map_err(archive_error)? {
+                let entry = entry.map_err(archive_error)?;
+                if !entry.header().entry_type().is_file() {
+                    continue
+                }
+                let member_path = entry.path().map_err(archive_error)?.to_string_lossy().into_owned();
+                search(&member_path, Box::new(entry))?;
+            }
+        }
+        ArchiveKind::Zip => {
+            let mut archive = zip::ZipArchive::new(file).map_err(|error| archive_error(error.into()))?;
+            for index in 0..archive.len() {
+                let entry = archive.by_index(index).map_err(|error| archive_error(error.into()))?;
+                if !entry.is_file() {
+                    continue
+                }
+                let member_path = entry.name().to_string();
+                search(&member_path, Box::new(entry))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" | "tty" | "if-tty" => Ok(ColorChoice::Auto),
+            "always" | "yes" | "force" => Ok(ColorChoice::Always),
+            "never" | "no" | "none" => Ok(ColorChoice::Never),
+            _ => Err(format!("invalid color choice '{}' (expected auto, always or never)", s)),
+        }
+    }
+}
+
+impl ColorChoice {
+    // "auto" only colors output to a terminal, and respects the NO_COLOR convention
+    // (see https://no-color.org)
+    pub fn use_colors(&self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+// SGR sequences (e.g. "01;31") for the different parts of the output, using the same
+// names and defaults as GNU grep's GREP_COLORS
+pub struct Colors {
+    pub selected_match: String,
+    pub context_match: String,
+    pub file_name: String,
+    pub line_number: String,
+    pub byte_offset: String,
+    pub separator: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            selected_match: String::from("01;31"),
+            context_match: String::from("01;31"),
+            file_name: String::from("35"),
+            line_number: String::from("32"),
+            byte_offset: String::from("32"),
+            separator: String::from("36"),
+        }
+    }
+}
+
+impl Colors {
+    // parses a GREP_COLORS value like "ms=01;31:fn=35:ln=32", starting from the defaults.
+    // Unknown capabilities are ignored, like GNU grep does.
+    pub fn from_grep_colors(spec: &str) -> Self {
+        let mut colors = Colors::default();
+        for capability in spec.split(':') {
+            let (name, value) = match capability.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                None => continue,
+            };
+            match name {
+                "mt" => {
+                    colors.selected_match = value.clone();
+                    colors.context_match = value;
+                }
+                "ms" => colors.selected_match = value,
+                "mc" => colors.context_match = value,
+                "fn" => colors.file_name = value,
+                "ln" => colors.line_number = value,
+                "bn" => colors.byte_offset = value,
+                "se" => colors.separator = value,
+                _ => { /* NOP */ }
+            }
+        }
+        colors
+    }
+
+    pub fn from_env() -> Self {
+        match std::env::var("GREP_COLORS") {
+            Ok(spec) => Colors::from_grep_colors(&spec),
+            Err(_) => Colors::default(),
+        }
+    }
+}
+
+// wraps the text in the given SGR sequence, an empty sequence leaves the text uncolored
+pub fn paint(sgr: &str, text: &[u8]) -> Vec<u8> {
+    if sgr.is_empty() {
+        return text.to_vec();
+    }
+    let mut painted = format!("\x1b[{}m\x1b[K", sgr).into_bytes();
+    painted.extend(text);
+    painted.extend(b"\x1b[m\x1b[K");
+    painted
+}
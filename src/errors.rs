@@ -0,0 +1,39 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+pub enum Error {
+    // invalid command line arguments or patterns, nothing is searched
+    Usage(String),
+    // a file or directory that could not be searched, the other files are still searched
+    Io { path: String, error: io::Error },
+    // standard output could not be written, nothing more is searched
+    Output(io::Error),
+}
+
+impl Error {
+    pub fn io(path: &str, error: io::Error) -> Self {
+        Error::Io { path: path.to_string(), error }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Io { path, error } => write!(f, "{}: {}", path, error),
+            Error::Output(error) => write!(f, "write error: {}", error),
+        }
+    }
+}
+
+// prints the error on standard error, prefixed with the name of the program, like GNU grep
+pub fn report(error: &Error) {
+    eprintln!("{}: {}", program_name(), error);
+}
+
+fn program_name() -> String {
+    std::env::args_os().next()
+        .and_then(|arg0| Path::new(&arg0).file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| String::from("grep"))
+}
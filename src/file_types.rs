@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+// the built-in file types, as (name, globs matching the file names of that type)
+const DEFAULT_FILE_TYPES : &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"]),
+    ("csharp", &["*.cs"]),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("go", &["*.go"]),
+    ("html", &["*.htm", "*.html"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("sql", &["*.sql"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml", "*.xsd", "*.xsl"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+// named sets of file name globs, used by --type and --type-not
+pub struct FileTypes {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl FileTypes {
+    pub fn new() -> Self {
+        let types = DEFAULT_FILE_TYPES.iter()
+            .map(|(name, globs)| (name.to_string(), globs.iter().map(|glob| glob.to_string()).collect()))
+            .collect();
+        FileTypes { types }
+    }
+
+    // adds a glob to a (possibly new) type, given as "name:glob" like for --type-add
+    pub fn add(&mut self, definition: &str) -> Result<(), String> {
+        match definition.split_once(':') {
+            Some((name, glob)) if !name.is_empty() && !glob.is_empty() => {
+                self.types.entry(name.to_string()).or_default().push(glob.to_string());
+                Ok(())
+            }
+            _ => Err(format!("invalid file type definition '{}' (expected name:glob)", definition)),
+        }
+    }
+
+    pub fn globs(&self, name: &str) -> Result<&[String], String> {
+        match self.types.get(name) {
+            Some(globs) => Ok(globs),
+            None => Err(format!("unknown file type '{}' (see --type-list)", name)),
+        }
+    }
+
+    // iterates over (name, globs) in alphabetical order of the names
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.types.iter().map(|(name, globs)| (&name[..], &globs[..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_extends_existing_types_and_creates_new_ones() {
+        let mut file_types = FileTypes::new();
+        file_types.add("rust:*.rs.in").unwrap();
+        file_types.add("proto:*.proto").unwrap();
+        assert_eq!(file_types.globs("rust").unwrap(), ["*.rs", "*.rs.in"]);
+        assert_eq!(file_types.globs("proto").unwrap(), ["*.proto"]);
+    }
+
+    #[test]
+    fn add_splits_at_the_first_colon() {
+        let mut file_types = FileTypes::new();
+        file_types.add("odd:a:b").unwrap();
+        assert_eq!(file_types.globs("odd").unwrap(), ["a:b"]);
+    }
+
+    #[test]
+    fn add_rejects_incomplete_definitions() {
+        let mut file_types = FileTypes::new();
+        for definition in ["proto", ":*.proto", "proto:", ""] {
+            assert!(file_types.add(definition).is_err(), "definition {:?}", definition);
+        }
+        assert!(file_types.globs("proto").is_err());
+    }
+
+    #[test]
+    fn iter_lists_the_types_alphabetically() {
+        let mut file_types = FileTypes::new();
+        file_types.add("aaa:*.a").unwrap();
+        let names: Vec<&str> = file_types.iter().map(|(name, _globs)| name).collect();
+        assert_eq!(names.first(), Some(&"aaa"));
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -1,91 +1,337 @@
 use std::collections::BTreeMap;
 use std::collections::btree_map;
+use std::io;
+use std::io::Write;
 
-pub trait HitHandler<'f> {
-    fn start_new_file(&mut self, file_path: &'f str);
-    fn handle_hit(&mut self, file_path: &'f str, line: usize, hit: &str);
+use crate::coloring;
+use crate::coloring::Colors;
+
+// the methods return errors writing the output
+pub trait HitHandler {
+    fn start_new_file(&mut self, file_path: &str) -> io::Result<()>;
+    // `offset` is the byte offset of the start of the line within the file. `terminated` is false
+    // if `hit` is the last line and did not end with a "\n". `matches` holds the byte offsets
+    // (start, end) of the matches within `hit`, which is empty for hits selected by --invert-match
+    fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()>;
+    // called for non-selected lines surrounding a hit, if context lines were requested.
+    // `matches` is only non-empty with --invert-match, where context lines are the matching ones.
+    fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()>;
+    // called after searching a file, with the number of bytes that were read from it
+    #[allow(unused_variables)]
+    fn finish_file(&mut self, file_path: &str, bytes_searched: usize) -> io::Result<()> { Ok(()) }
 }
 
 pub struct HitPrinter {
     print_file_path: bool,
     print_line: bool,
     print_hit: bool,
+    only_matching: bool,
+    print_byte_offset: bool,
+    print_column: bool,
+    // printed between non-adjacent groups of lines, only set if context lines were requested
+    group_separator: Option<String>,
+    colors: Option<Colors>,
+    printed_lines: bool,
+    last_line: Option<usize>,
 }
 
 impl HitPrinter {
-    pub fn new(print_file_path: bool, print_line: bool, print_hit: bool) -> Self {
+    pub fn new(print_file_path: bool, print_line: bool, print_hit: bool, only_matching: bool) -> Self {
         HitPrinter {
             print_file_path: print_file_path,
             print_line: print_line,
-            print_hit: print_hit
+            print_hit: print_hit,
+            only_matching,
+            print_byte_offset: false,
+            print_column: false,
+            group_separator: None,
+            colors: None,
+            printed_lines: false,
+            last_line: None,
         }
     }
+
+    pub fn with_byte_offset(mut self, print_byte_offset: bool) -> Self {
+        self.print_byte_offset = print_byte_offset;
+        self
+    }
+
+    pub fn with_column(mut self, print_column: bool) -> Self {
+        self.print_column = print_column;
+        self
+    }
+
+    pub fn with_group_separator(mut self, group_separator: Option<String>) -> Self {
+        self.group_separator = group_separator;
+        self
+    }
+
+    pub fn with_colors(mut self, colors: Option<Colors>) -> Self {
+        self.colors = colors;
+        self
+    }
 }
 
-impl<'f> HitHandler<'f> for HitPrinter {
+impl HitHandler for HitPrinter {
+    #[allow(unused_variables)]
+    fn start_new_file(&mut self, file_path: &str) -> io::Result<()> {
+        self.last_line = None;
+        Ok(())
+    }
+    // like GNU grep, a "\n" is printed even after a last line without one
+    #[allow(unused_variables)]
+    fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+        self.start_line(line)?;
+        if self.only_matching {
+            // the column and byte offset are those of the match
+            for (start, end) in matches.iter().filter(|(start, end)| start < end) {
+                let position = Position { line, column: start + 1, offset: offset + start };
+                let hit = &hit[*start..*end];
+                self.print(file_path, position, hit, &[(0, hit.len())], ':')?;
+            }
+            Ok(())
+        } else {
+            let position = Position { line, column: first_column(matches), offset };
+            self.print(file_path, position, hit, matches, ':')
+        }
+    }
     #[allow(unused_variables)]
-    fn start_new_file(&mut self, file_path: &'f str) { /* NOP */ }
+    fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+        // with --only-matching, context lines are not printed (like in GNU grep), but still
+        // separate the groups of hits
+        self.start_line(line)?;
+        if self.only_matching {
+            return Ok(())
+        }
+        let position = Position { line, column: first_column(matches), offset };
+        self.print(file_path, position, context, matches, '-')
+    }
+}
+
+// where a printed line (or, with --only-matching, a match) starts
+struct Position {
+    line: usize,
+    // one-based, in bytes
+    column: usize,
+    offset: usize,
+}
+
+// the column of the first match, or 1 for lines without matches (e.g. context lines)
+fn first_column(matches: &[(usize, usize)]) -> usize {
+    matches.first().map_or(1, |(start, _end)| start + 1)
+}
+
+impl HitPrinter {
+    // prints the group separator if the given line does not directly follow the previously printed one
+    fn start_line(&mut self, line: usize) -> io::Result<()> {
+        match self.separator_before(line) {
+            Some(separator) => write_to_stdout(&separator),
+            None => Ok(()),
+        }
+    }
+
+    // returns the group separator line to print before the given line, if any
+    fn separator_before(&mut self, line: usize) -> Option<Vec<u8>> {
+        let adjacent = self.last_line.is_some_and(|last_line| line == last_line + 1);
+        let separate = self.printed_lines && !adjacent;
+        self.printed_lines = true;
+        self.last_line = Some(line);
+        let group_separator = self.group_separator.as_ref().filter(|_| separate)?;
+        let mut output = self.paint(|colors| &colors.separator, group_separator.as_bytes());
+        output.push(b'\n');
+        Some(output)
+    }
+
+    // `separator` is ':' for hits and '-' for context lines, like in GNU grep. The hit is
+    // written as it is, even if it is not valid UTF-8.
     #[allow(unused_assignments)]
-    fn handle_hit(&mut self, file_path: &'f str, line: usize, hit: &str) {
+    fn print(&self, file_path: &str, position: Position, hit: &[u8], matches: &[(usize, usize)], separator: char) -> io::Result<()> {
+        let selected = separator == ':';
+        let separator = self.paint(|colors| &colors.separator, separator.to_string().as_bytes());
+        let mut output = Vec::new();
         let mut have_content = false;
         if self.print_file_path {
-            print!("{}", file_path);
+            output.extend(self.paint(|colors| &colors.file_name, file_path.as_bytes()));
             have_content = true;
         }
         if self.print_line {
-            if have_content { print!(":") };
-            print!("{}", line);
+            if have_content { output.extend(&separator) };
+            output.extend(self.paint(|colors| &colors.line_number, position.line.to_string().as_bytes()));
+            have_content = true;
+        }
+        if self.print_column {
+            if have_content { output.extend(&separator) };
+            output.extend(self.paint(|colors| &colors.line_number, position.column.to_string().as_bytes()));
+            have_content = true;
+        }
+        if self.print_byte_offset {
+            if have_content { output.extend(&separator) };
+            output.extend(self.paint(|colors| &colors.byte_offset, position.offset.to_string().as_bytes()));
             have_content = true;
         }
         if self.print_hit {
-            if have_content { print!(":"); };
-            print!("{}", hit);
+            if have_content { output.extend(&separator); };
+            output.extend(self.highlight(hit, matches, selected));
             have_content = true;
         }
-        print!("\n");
+        output.push(b'\n');
+        write_to_stdout(&output)
+    }
+
+    fn paint(&self, color: impl Fn(&Colors) -> &String, text: &[u8]) -> Vec<u8> {
+        match &self.colors {
+            Some(colors) => coloring::paint(color(colors), text),
+            None => text.to_vec(),
+        }
+    }
+
+    // colors the matched parts of a line, either as those of a selected line or of a context line
+    fn highlight(&self, text: &[u8], matches: &[(usize, usize)], selected: bool) -> Vec<u8> {
+        let colors = match &self.colors {
+            Some(colors) => colors,
+            None => return text.to_vec(),
+        };
+        let sgr = match selected {
+            true => &colors.selected_match,
+            false => &colors.context_match,
+        };
+        let mut highlighted = Vec::new();
+        let mut last_end = 0;
+        for (start, end) in matches.iter().filter(|(start, end)| start < end) {
+            highlighted.extend(&text[last_end..*start]);
+            highlighted.extend(coloring::paint(sgr, &text[*start..*end]));
+            last_end = *end;
+        }
+        highlighted.extend(&text[last_end..]);
+        highlighted
+    }
+}
+
+// For --vimgrep: prints "file:line:column:text" for each match, the format understood by Vim's
+// 'grepformat' and editor problem matchers. Lines with several matches are printed several times.
+pub struct VimgrepPrinter {
+    only_matching: bool,
+}
+
+impl VimgrepPrinter {
+    pub fn new(only_matching: bool) -> Self {
+        VimgrepPrinter { only_matching }
+    }
+
+    fn print(&self, file_path: &str, line: usize, column: usize, text: &[u8]) -> io::Result<()> {
+        let mut output = format!("{}:{}:{}:", file_path, line, column).into_bytes();
+        output.extend(text);
+        output.push(b'\n');
+        write_to_stdout(&output)
+    }
+}
+
+impl HitHandler for VimgrepPrinter {
+    #[allow(unused_variables)]
+    fn start_new_file(&mut self, file_path: &str) -> io::Result<()> { Ok(()) }
+    #[allow(unused_variables)]
+    fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+        let non_empty_matches: Vec<&(usize, usize)> = matches.iter().filter(|(start, end)| start < end).collect();
+        // lines selected by --invert-match (or only matched by empty matches) are printed once
+        if non_empty_matches.is_empty() {
+            self.print(file_path, line, first_column(matches), hit)?;
+        }
+        for (start, end) in non_empty_matches {
+            let text = match self.only_matching {
+                true => &hit[*start..*end],
+                false => hit,
+            };
+            self.print(file_path, line, start + 1, text)?;
+        }
+        Ok(())
     }
+    #[allow(unused_variables)]
+    fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> { Ok(()) }
+}
+
+// like print!, but for bytes, and returning errors instead of panicking
+pub fn write_to_stdout(bytes: &[u8]) -> io::Result<()> {
+    io::stdout().lock().write_all(bytes)
 }
 
-pub struct HitCounter<'f> {
-    hits: BTreeMap<&'f str, usize>
+// file paths are owned, since they may be generated while searching (e.g. for archive members)
+pub struct HitCounter {
+    hits: BTreeMap<String, usize>
 }
 
-impl<'a, 'f> HitCounter<'f> {
+impl<'a> HitCounter {
     pub fn new() -> Self { HitCounter { hits : BTreeMap::new() } }
-    pub fn iter(&'a self) -> HitCounterIter<'a, 'f> {
+    pub fn iter(&'a self) -> HitCounterIter<'a> {
         self.into_iter()
     }
 }
-impl<'f> HitHandler<'f> for HitCounter<'f> {
-    fn start_new_file(&mut self, file_path: &'f str) {
-        self.hits.insert(file_path, 0);
+impl HitHandler for HitCounter {
+    fn start_new_file(&mut self, file_path: &str) -> io::Result<()> {
+        self.hits.insert(file_path.to_string(), 0);
+        Ok(())
     }
     #[allow(unused_variables)]
-    fn handle_hit(&mut self, file_path: &'f str, line: usize, hit: &str) {
+    fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
         *self.hits.get_mut(file_path).unwrap() += 1;
+        Ok(())
     }
+    #[allow(unused_variables)]
+    fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> { Ok(()) }
 }
-impl<'a, 'f> IntoIterator for &'a HitCounter<'f> {
-    type Item = (&'f str, usize);
-    type IntoIter = HitCounterIter<'a, 'f>;
+impl<'a> IntoIterator for &'a HitCounter {
+    type Item = (&'a str, usize);
+    type IntoIter = HitCounterIter<'a>;
     fn into_iter(self) -> Self::IntoIter {
         HitCounterIter::from_hit_counter(self)
     }
 }
 
 // utility struct to allow iterating over items of type
-// (&'f str, usize) instead of (&'a &'f str, &'a usize)
-pub struct HitCounterIter<'a, 'f> {
-    iter: btree_map::Iter<'a, &'f str, usize>
+// (&'a str, usize) instead of (&'a String, &'a usize)
+pub struct HitCounterIter<'a> {
+    iter: btree_map::Iter<'a, String, usize>
 }
-impl<'a, 'f> HitCounterIter<'a, 'f> {
-    fn from_hit_counter(hit_counter: &'a HitCounter<'f>) -> Self {
+impl<'a> HitCounterIter<'a> {
+    fn from_hit_counter(hit_counter: &'a HitCounter) -> Self {
         HitCounterIter { iter: (&hit_counter.hits).into_iter() }
     }
 }
-impl<'a, 'f> Iterator for HitCounterIter<'a, 'f> {
-    type Item = (&'f str, usize);
+impl<'a> Iterator for HitCounterIter<'a> {
+    type Item = (&'a str, usize);
     fn next(&mut self) -> Option<Self::Item>{
-        self.iter.next().map(|(k, v)| (*k, *v))
+        self.iter.next().map(|(k, v)| (&k[..], *v))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printer(group_separator: Option<&str>) -> HitPrinter {
+        HitPrinter::new(false, false, true, false).with_group_separator(group_separator.map(String::from))
+    }
+
+    #[test]
+    fn group_separators_are_printed_between_non_adjacent_lines() {
+        let mut printer = printer(Some("--"));
+        assert_eq!(printer.separator_before(3), None);
+        assert_eq!(printer.separator_before(4), None);
+        assert_eq!(printer.separator_before(7), Some(b"--\n".to_vec()));
+        assert_eq!(printer.separator_before(8), None);
+    }
+
+    #[test]
+    fn group_separators_are_printed_between_files() {
+        let mut printer = printer(Some("::"));
+        assert_eq!(printer.separator_before(1), None);
+        printer.start_new_file("other").unwrap();
+        assert_eq!(printer.separator_before(2), Some(b"::\n".to_vec()));
+    }
+
+    #[test]
+    fn no_group_separators_without_context() {
+        let mut printer = printer(None);
+        assert_eq!(printer.separator_before(1), None);
+        assert_eq!(printer.separator_before(5), None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+// The rules of a single ignore file (.gitignore, .ignore, ...), following the gitignore
+// format: one glob pattern per line, "#" comments, "!" to re-include previously ignored
+// paths, a trailing "/" to only match directories. Patterns containing a "/" are relative
+// to the directory of the ignore file, all others match file names at any depth.
+pub struct IgnoreFile {
+    // the directory the patterns are relative to
+    base: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+struct IgnoreRule {
+    negated: bool,
+    only_dirs: bool,
+    anchored: bool,
+    components: Vec<PatternComponent>,
+}
+
+enum PatternComponent {
+    // "**", matches any number of directories
+    AnyDirs,
+    Glob(glob::ParsedGlobString),
+}
+
+impl IgnoreFile {
+    // returns None if the file does not exist or cannot be read
+    pub fn load(path: &Path, base: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        Some(IgnoreFile::parse(&contents, base))
+    }
+
+    // lines that cannot be parsed are skipped, like git does
+    pub fn parse(contents: &str, base: &Path) -> Self {
+        let rules = contents.lines().filter_map(IgnoreRule::parse).collect();
+        IgnoreFile { base: base.to_path_buf(), rules }
+    }
+
+    // returns Some(true) if the (absolute) path is ignored, Some(false) if it is explicitly
+    // re-included and None if no rule applies to it. Later rules take precedence.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let components: Vec<String> = relative.components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let components: Vec<&str> = components.iter().map(|component| &component[..]).collect();
+        self.rules.iter().rev()
+            .find(|rule| rule.matches(&components, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        // trailing spaces are ignored, unless they are escaped with a backslash
+        let mut line = line.trim_end_matches('\r');
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let negated = line.starts_with('!');
+        if negated {
+            line = &line[1..];
+        }
+        let only_dirs = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        if line.is_empty() {
+            return None;
+        }
+        let anchored = line.contains('/');
+        let components = line.trim_start_matches('/').split('/')
+            .map(|component| match component {
+                "**" => Some(PatternComponent::AnyDirs),
+                _ => glob::ParsedGlobString::try_from(component).ok().map(PatternComponent::Glob),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(IgnoreRule { negated, only_dirs, anchored, components })
+    }
+
+    fn matches(&self, path: &[&str], is_dir: bool) -> bool {
+        if self.only_dirs && !is_dir {
+            return false;
+        }
+        match self.anchored {
+            true => match_components(&self.components, path),
+            false => path.last().is_some_and(|name| match_components(&self.components, &[*name])),
+        }
+    }
+}
+
+// matches the pattern against the path one component at a time, so that wildcards
+// never match a "/"
+fn match_components(pattern: &[PatternComponent], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((PatternComponent::AnyDirs, pattern_rest)) => {
+            (0..=path.len()).any(|skipped| match_components(pattern_rest, &path[skipped..]))
+        }
+        Some((PatternComponent::Glob(glob), pattern_rest)) => match path.split_first() {
+            Some((name, path_rest)) => glob.matches(name) && match_components(pattern_rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+// the user's global gitignore: core.excludesFile from ~/.gitconfig, or git's default location
+pub fn global_gitignore_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if let Some(home) = &home {
+        if let Ok(config) = fs::read_to_string(home.join(".gitconfig")) {
+            for line in config.lines() {
+                if let Some((key, value)) = line.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("excludesfile") {
+                        let value = value.trim().trim_matches('"');
+                        return match value.strip_prefix("~/") {
+                            Some(relative) => Some(home.join(relative)),
+                            None => Some(PathBuf::from(value)),
+                        };
+                    }
+                }
+            }
+        }
+    }
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_dir) if !config_dir.is_empty() => Some(PathBuf::from(config_dir).join("git/ignore")),
+        _ => home.map(|home| home.join(".config/git/ignore")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_ignored(contents: &str, path: &str, is_dir: bool) -> Option<bool> {
+        IgnoreFile::parse(contents, Path::new("/repo")).is_ignored(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        assert!(IgnoreRule::parse("").is_none());
+        assert!(IgnoreRule::parse("   ").is_none());
+        assert!(IgnoreRule::parse("# comment").is_none());
+        assert!(IgnoreRule::parse("/").is_none());
+    }
+
+    #[test]
+    fn parse_reads_negation_directories_and_anchoring() {
+        let rule = IgnoreRule::parse("!build/  ").unwrap();
+        assert!(rule.negated && rule.only_dirs && !rule.anchored);
+        let rule = IgnoreRule::parse("/top.txt").unwrap();
+        assert!(!rule.negated && !rule.only_dirs && rule.anchored);
+        assert_eq!(rule.components.len(), 1);
+        let rule = IgnoreRule::parse("a/**/b").unwrap();
+        assert!(rule.anchored);
+        assert!(matches!(rule.components[1], PatternComponent::AnyDirs));
+    }
+
+    #[test]
+    fn unanchored_patterns_match_names_at_any_depth() {
+        assert_eq!(is_ignored("*.log", "/repo/x.log", false), Some(true));
+        assert_eq!(is_ignored("*.log", "/repo/a/b/x.log", false), Some(true));
+        assert_eq!(is_ignored("*.log", "/repo/x.txt", false), None);
+        assert_eq!(is_ignored("*.log", "/elsewhere/x.log", false), None);
+    }
+
+    #[test]
+    fn anchored_patterns_match_relative_to_the_base() {
+        assert_eq!(is_ignored("/top.txt", "/repo/top.txt", false), Some(true));
+        assert_eq!(is_ignored("/top.txt", "/repo/sub/top.txt", false), None);
+        assert_eq!(is_ignored("a/*.rs", "/repo/a/x.rs", false), Some(true));
+        assert_eq!(is_ignored("a/*.rs", "/repo/a/b/x.rs", false), None);
+    }
+
+    #[test]
+    fn match_components_supports_any_dirs() {
+        assert_eq!(is_ignored("a/**/b", "/repo/a/b", false), Some(true));
+        assert_eq!(is_ignored("a/**/b", "/repo/a/x/y/b", false), Some(true));
+        assert_eq!(is_ignored("a/**/b", "/repo/c/a/b", false), None);
+        assert_eq!(is_ignored("**/b", "/repo/x/b", false), Some(true));
+    }
+
+    #[test]
+    fn directory_patterns_only_match_directories() {
+        assert_eq!(is_ignored("build/", "/repo/build", true), Some(true));
+        assert_eq!(is_ignored("build/", "/repo/build", false), None);
+    }
+
+    #[test]
+    fn later_rules_take_precedence() {
+        assert_eq!(is_ignored("*.log\n!keep.log", "/repo/keep.log", false), Some(false));
+        assert_eq!(is_ignored("*.log\n!keep.log", "/repo/other.log", false), Some(true));
+        assert_eq!(is_ignored("!keep.log\n*.log", "/repo/keep.log", false), Some(true));
+    }
+}
@@ -0,0 +1,231 @@
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+use std::process::Child;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::thread;
+
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+// how to treat files that look like binary data (--binary-files)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFiles {
+    // search them, but only report whether they match
+    Binary,
+    // search them like text files
+    Text,
+    // treat them as if they did not match
+    WithoutMatch,
+}
+
+impl FromStr for BinaryFiles {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(BinaryFiles::Binary),
+            "text" => Ok(BinaryFiles::Text),
+            "without-match" => Ok(BinaryFiles::WithoutMatch),
+            _ => Err(format!("invalid binary files type '{}' (expected binary, text or without-match)", s)),
+        }
+    }
+}
+
+// looks up an encoding by its WHATWG label (e.g. "utf-16le", "latin1" or "shift_jis").
+// "auto" means no explicit encoding, i.e. only byte order marks are taken into account.
+pub fn encoding_for_label(label: &str) -> Result<Option<&'static Encoding>, String> {
+    if label.eq_ignore_ascii_case("auto") {
+        return Ok(None);
+    }
+    match Encoding::for_label(label.as_bytes()) {
+        Some(encoding) => Ok(Some(encoding)),
+        None => Err(format!("unknown encoding '{}'", label)),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    fn from_magic_number(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+// For --search-zip: wraps input that is compressed with gzip, bzip2, xz or zstd in a streaming
+// decompressor. The format is recognized by its magic number only, so that files which merely
+// have the extension of a compressed file are searched as they are.
+pub fn decompressing_reader<'r>(source: Box<dyn Read + 'r>) -> io::Result<Box<dyn Read + 'r>> {
+    let mut source = BufReader::new(source);
+    let compression = Compression::from_magic_number(source.fill_buf()?);
+    let reader: Box<dyn Read + 'r> = match compression {
+        None => Box::new(source),
+        Some(Compression::Gzip) => Box::new(flate2::read::MultiGzDecoder::new(source)),
+        Some(Compression::Bzip2) => Box::new(bzip2::read::MultiBzDecoder::new(source)),
+        Some(Compression::Xz) => Box::new(xz2::read::XzDecoder::new_multi_decoder(source)),
+        Some(Compression::Zstd) => Box::new(zstd::stream::read::Decoder::with_buffer(source)?),
+    };
+    Ok(reader)
+}
+
+// For --pre: runs `command` with the path of the file as its only argument (and the file as its
+// standard input), and returns the output of the command instead of the contents of the file.
+// The output is streamed, so reading fails at its end if the command exits unsuccessfully (and
+// keeps failing, so that the error is not lost if it is first hit by a look-ahead).
+pub fn preprocessed_reader(command: &str, path: &Path, file: std::fs::File) -> io::Result<Box<dyn Read>> {
+    let mut child = Command::new(command)
+        .arg(path)
+        .stdin(Stdio::from(file))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| io::Error::new(error.kind(), format!("could not run {}: {}", command, error)))?;
+    let stdout = child.stdout.take().unwrap();
+    // collected concurrently, so that the command cannot block on a full pipe
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stderr.read_to_end(&mut output);
+        output
+    });
+    Ok(Box::new(PreprocessedReader {
+        command: command.to_string(),
+        child,
+        stdout: Some(stdout),
+        stderr: Some(stderr),
+        failure: None,
+    }))
+}
+
+struct PreprocessedReader {
+    command: String,
+    child: Child,
+    // None once the end of the output has been reached
+    stdout: Option<ChildStdout>,
+    stderr: Option<thread::JoinHandle<Vec<u8>>>,
+    // set at the end of the output if the command failed, returned by every read from then on
+    failure: Option<String>,
+}
+
+impl PreprocessedReader {
+    // returns why the command failed, if it did
+    fn finish(&mut self) -> Option<String> {
+        let status = match self.child.wait() {
+            Ok(status) => status,
+            Err(error) => return Some(format!("{} failed: {}", self.command, error)),
+        };
+        let stderr = self.stderr.take().and_then(|stderr| stderr.join().ok()).unwrap_or_default();
+        match status.success() {
+            true => None,
+            false => {
+                let stderr = String::from_utf8_lossy(&stderr);
+                Some(format!("{} failed ({}): {}", self.command, status, stderr.trim_end()))
+            }
+        }
+    }
+}
+
+impl Read for PreprocessedReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if let Some(failure) = &self.failure {
+            return Err(io::Error::other(failure.clone()));
+        }
+        let stdout = match &mut self.stdout {
+            Some(stdout) => stdout,
+            None => return Ok(0),
+        };
+        let read = stdout.read(buffer)?;
+        if read == 0 && !buffer.is_empty() {
+            self.stdout = None;
+            self.failure = self.finish();
+            if let Some(failure) = &self.failure {
+                return Err(io::Error::other(failure.clone()));
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for PreprocessedReader {
+    // if the output was not read to its end (e.g. because of --max-count), the command is
+    // stopped, and whether it succeeds does not matter anymore
+    fn drop(&mut self) {
+        if self.stdout.take().is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+// Transcodes the input to UTF-8, if it starts with a UTF-8 or UTF-16 byte order mark (which
+// takes precedence) or an encoding is given. Otherwise, the bytes are passed through unchanged.
+pub fn decoding_reader<'r>(source: Box<dyn Read + 'r>, encoding: Option<&'static Encoding>) -> Box<dyn BufRead + 'r> {
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .bom_override(true)
+        .utf8_passthru(true)
+        .build(source);
+    Box::new(BufReader::new(decoder))
+}
+
+// A file is considered binary if its first block contains a NUL byte, like GNU grep does.
+// The block stays buffered, so the file can still be searched afterwards. This must be
+// checked after decoding, since UTF-16 encoded text is full of NUL bytes.
+pub fn is_binary(reader: &mut dyn BufRead) -> io::Result<bool> {
+    Ok(memchr::memchr(0, reader.fill_buf()?).is_some())
+}
+
+pub fn byte_lines<R: BufRead>(reader: R) -> ByteLines<R> {
+    ByteLines { reader }
+}
+
+// like BufRead::lines, but yields the raw bytes of each line, so that input that is not
+// valid UTF-8 can be searched (and printed unchanged). Only the "\n" is stripped from the
+// lines, a preceding "\r" is kept, so that printed lines are identical to the input.
+pub struct ByteLines<R> {
+    reader: R,
+}
+
+pub struct Line {
+    pub bytes: Vec<u8>,
+    // whether the line ended with a "\n", which only the last line of the input may not
+    pub terminated: bool,
+}
+
+impl<R: BufRead> Iterator for ByteLines<R> {
+    type Item = io::Result<Line>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buffer = Vec::new();
+        match self.reader.read_until(b'\n', &mut buffer) {
+            Ok(0) => None,
+            Ok(_) => {
+                let terminated = buffer.ends_with(b"\n");
+                if terminated {
+                    buffer.pop();
+                }
+                Some(Ok(Line { bytes: buffer, terminated }))
+            }
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
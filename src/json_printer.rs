@@ -0,0 +1,252 @@
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+use base64::Engine;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::hit_handling::HitHandler;
+
+// For --json: prints one JSON object per line for each event, using the schema of ripgrep's
+// --json output, i.e. "begin", "match", "context" and "end" messages for each file, and a
+// final "summary" message. Like in ripgrep, files without any printed lines get neither a
+// "begin" nor an "end" message, but are still counted in the summary.
+pub struct JsonPrinter<W: Write> {
+    // standard output, except in tests
+    output: W,
+    started: Instant,
+    file_started: Instant,
+    // whether the "begin" message of the current file has been printed
+    file_begun: bool,
+    // of the file currently being searched
+    file_stats: Stats,
+    total_stats: Stats,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Stats {
+    elapsed: Duration,
+    searches: usize,
+    searches_with_match: usize,
+    bytes_searched: usize,
+    bytes_printed: usize,
+    matched_lines: usize,
+    matches: usize,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.bytes_printed += other.bytes_printed;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "elapsed": duration(self.elapsed),
+            "searches": self.searches,
+            "searches_with_match": self.searches_with_match,
+            "bytes_searched": self.bytes_searched,
+            "bytes_printed": self.bytes_printed,
+            "matched_lines": self.matched_lines,
+            "matches": self.matches,
+        })
+    }
+}
+
+impl<W: Write> JsonPrinter<W> {
+    pub fn new(output: W) -> Self {
+        JsonPrinter {
+            output,
+            started: Instant::now(),
+            file_started: Instant::now(),
+            file_begun: false,
+            file_stats: Stats::default(),
+            total_stats: Stats::default(),
+        }
+    }
+
+    // prints the "summary" message, after all files have been searched
+    pub fn print_summary(&mut self) -> io::Result<()> {
+        self.total_stats.elapsed = self.started.elapsed();
+        let message = json!({
+            "type": "summary",
+            "data": {
+                "elapsed_total": duration(self.total_stats.elapsed),
+                "stats": self.total_stats.to_json(),
+            },
+        });
+        self.print(&message)
+    }
+
+    fn print(&mut self, message: &Value) -> io::Result<()> {
+        let mut output = serde_json::to_vec(message).expect("failed serializing JSON");
+        output.push(b'\n');
+        self.output.write_all(&output)?;
+        self.file_stats.bytes_printed += output.len();
+        Ok(())
+    }
+
+    // `kind` is either "match" or "context", `lines` includes the line terminator, if any
+    fn print_line(&mut self, kind: &str, file_path: &str, line: usize, offset: usize, lines: &[u8], matches: &[(usize, usize)]) -> io::Result<()> {
+        if !self.file_begun {
+            self.print(&json!({ "type": "begin", "data": { "path": { "text": file_path } } }))?;
+            self.file_begun = true;
+        }
+        let submatches: Vec<Value> = matches.iter()
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| json!({ "match": data(&lines[*start..*end]), "start": start, "end": end }))
+            .collect();
+        let message = json!({
+            "type": kind,
+            "data": {
+                "path": { "text": file_path },
+                "lines": data(lines),
+                "line_number": line,
+                "absolute_offset": offset,
+                "submatches": submatches,
+            },
+        });
+        self.print(&message)
+    }
+}
+
+impl<W: Write> HitHandler for JsonPrinter<W> {
+    #[allow(unused_variables)]
+    fn start_new_file(&mut self, file_path: &str) -> io::Result<()> {
+        self.file_started = Instant::now();
+        self.file_stats = Stats { searches: 1, ..Stats::default() };
+        self.file_begun = false;
+        Ok(())
+    }
+    fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+        self.file_stats.matched_lines += 1;
+        self.file_stats.matches += matches.iter().filter(|(start, end)| start < end).count();
+        self.print_line("match", file_path, line, offset, &with_terminator(hit, terminated), matches)
+    }
+    fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+        self.print_line("context", file_path, line, offset, &with_terminator(context, terminated), matches)
+    }
+    fn finish_file(&mut self, file_path: &str, bytes_searched: usize) -> io::Result<()> {
+        self.file_stats.elapsed = self.file_started.elapsed();
+        self.file_stats.bytes_searched = bytes_searched;
+        self.file_stats.searches_with_match = (self.file_stats.matched_lines > 0) as usize;
+        if self.file_begun {
+            let message = json!({
+                "type": "end",
+                "data": {
+                    "path": { "text": file_path },
+                    "binary_offset": null,
+                    "stats": self.file_stats.to_json(),
+                },
+            });
+            self.print(&message)?;
+        }
+        self.total_stats.add(&self.file_stats);
+        Ok(())
+    }
+}
+
+// the line terminator is part of the line in ripgrep's output
+fn with_terminator(line: &[u8], terminated: bool) -> Vec<u8> {
+    let mut lines = line.to_vec();
+    if terminated {
+        lines.push(b'\n');
+    }
+    lines
+}
+
+// arbitrary data is represented as text if it is valid UTF-8, and base64 encoded otherwise
+fn data(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "bytes": base64::engine::general_purpose::STANDARD.encode(bytes) }),
+    }
+}
+
+fn duration(duration: Duration) -> Value {
+    json!({
+        "secs": duration.as_secs(),
+        "nanos": duration.subsec_nanos(),
+        "human": format!("{:.6}s", duration.as_secs_f64()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(printer: &JsonPrinter<Vec<u8>>) -> Vec<Value> {
+        printer.output.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<Value>(line).unwrap())
+            .collect()
+    }
+
+    fn types(messages: &[Value]) -> Vec<&str> {
+        messages.iter().map(|message| message["type"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn files_without_printed_lines_get_no_begin_and_end() {
+        let mut printer = JsonPrinter::new(Vec::new());
+        printer.start_new_file("a").unwrap();
+        printer.finish_file("a", 10).unwrap();
+        printer.start_new_file("b").unwrap();
+        printer.handle_hit("b", 1, 0, b"foo", true, &[(0, 3)]).unwrap();
+        printer.finish_file("b", 4).unwrap();
+        printer.print_summary().unwrap();
+        let messages = messages(&printer);
+        assert_eq!(types(&messages), ["begin", "match", "end", "summary"]);
+        assert_eq!(messages[0]["data"]["path"]["text"], "b");
+        // the file without hits is still counted
+        assert_eq!(messages[3]["data"]["stats"]["searches"], 2);
+        assert_eq!(messages[3]["data"]["stats"]["bytes_searched"], 14);
+    }
+
+    #[test]
+    fn context_lines_begin_a_file_too() {
+        let mut printer = JsonPrinter::new(Vec::new());
+        printer.start_new_file("a").unwrap();
+        printer.handle_context("a", 1, 0, b"bar", true, &[]).unwrap();
+        printer.finish_file("a", 4).unwrap();
+        assert_eq!(types(&messages(&printer)), ["begin", "context", "end"]);
+    }
+
+    #[test]
+    fn lines_keep_their_terminator_only_if_they_had_one() {
+        let mut printer = JsonPrinter::new(Vec::new());
+        printer.start_new_file("a").unwrap();
+        printer.handle_hit("a", 1, 0, b"foo", true, &[(0, 3)]).unwrap();
+        printer.handle_hit("a", 2, 4, b"foo", false, &[(0, 3)]).unwrap();
+        let messages = messages(&printer);
+        assert_eq!(messages[1]["data"]["lines"]["text"], "foo\n");
+        assert_eq!(messages[2]["data"]["lines"]["text"], "foo");
+        assert_eq!(messages[2]["data"]["absolute_offset"], 4);
+    }
+
+    #[test]
+    fn data_is_base64_encoded_unless_it_is_valid_utf8() {
+        assert_eq!(data("héllo".as_bytes())["text"], "héllo");
+        assert_eq!(data(b"\xFFab")["bytes"], "/2Fi");
+    }
+
+    #[test]
+    fn submatches_are_reported_with_their_offsets() {
+        let mut printer = JsonPrinter::new(Vec::new());
+        printer.start_new_file("a").unwrap();
+        printer.handle_hit("a", 1, 0, b"a foo", true, &[(2, 5), (5, 5)]).unwrap();
+        let messages = messages(&printer);
+        let submatches = messages[1]["data"]["submatches"].as_array().unwrap();
+        // empty matches are left out
+        assert_eq!(submatches.len(), 1);
+        assert_eq!(submatches[0]["match"]["text"], "foo");
+        assert_eq!(submatches[0]["start"], 2);
+        assert_eq!(submatches[0]["end"], 5);
+    }
+}
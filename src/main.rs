@@ -1,19 +1,56 @@
 use std::fs::File;
 use std::io::BufReader;
 use std::io::BufRead;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 
+use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
 
 mod hit_handling;
 use hit_handling::HitHandler;
 use hit_handling::HitPrinter;
 use hit_handling::HitCounter;
+use hit_handling::VimgrepPrinter;
+mod coloring;
+use coloring::ColorChoice;
+use coloring::Colors;
+mod walking;
+use walking::WalkOptions;
+mod ignore_files;
+mod path_filter;
+use path_filter::FilterKind;
+use path_filter::PathFilter;
+mod file_types;
+use file_types::FileTypes;
+mod input;
+use input::BinaryFiles;
+mod archives;
+use archives::ArchiveKind;
+mod searching;
+use searching::SearchOptions;
+mod errors;
+use errors::Error;
+mod json_printer;
+use json_printer::JsonPrinter;
+mod sarif_printer;
+use sarif_printer::SarifPrinter;
+mod matching;
+use matching::PatternSyntax;
+use matching::MatcherOptions;
 
-const PATTERN_HELP : &str = "a glob-style pattern to search for in the given files";
+const PATTERN_HELP : &str = concat!(
+    "a glob-style pattern to search for in the given files (or an extended regular expression, ",
+    "if --extended-regexp is given, or a literal string, if --fixed-strings is given). ",
+    "If --regexp or --file is given, this is treated as the first of the files instead."
+);
 const FILES_HELP : &str = concat!(
     "zero or more paths of files. Each file will be searched for occurences ",
     "of the given pattern. Use \"-\" to search standard input as if it were a file. ",
-    "If no files are given, search standard input."
+    "If no files are given, search standard input (or the current directory, with --recursive)."
 );
 const INVERT_MATCH_HELP : &str = concat!(
     "if given, print lines *not* matching the pattern instead of lines ",
@@ -33,6 +70,29 @@ const LINE_NUMBER_HELP : &str = concat!(
     "For each hit, print the line number (in the respective file) on which the match occured. ",
     "The line number count is one-based."
 );
+const BYTE_OFFSET_HELP : &str = concat!(
+    "For each hit, print the zero-based byte offset of the line within the file (or of the match, ",
+    "with --only-matching), after the line number."
+);
+const COLUMN_HELP : &str = concat!(
+    "For each hit, print the one-based column (in bytes) of the first match in the line (or of ",
+    "each match, with --only-matching), after the line number."
+);
+const VIMGREP_HELP : &str = concat!(
+    "Print \"FILE:LINE:COLUMN:LINE CONTENT\" for each match, as understood by Vim and editor problem ",
+    "matchers. A line with several matches is printed once per match. Context lines are not printed, ",
+    "and neither are the \"Binary file ... matches\" messages."
+);
+const JSON_HELP : &str = concat!(
+    "Print the results as JSON Lines, using the same messages as ripgrep's --json (\"begin\", ",
+    "\"match\", \"context\" and \"end\" for each file with printed lines, and a final \"summary\"). ",
+    "Data that is not valid UTF-8 is base64 encoded. Matching lines of binary files are printed as well."
+);
+const SARIF_HELP : &str = concat!(
+    "Print the results as a SARIF 2.1.0 log after searching all files, e.g. for code scanning in CI. ",
+    "Each pattern is a rule (with the ids e1, e2, ... in the order the patterns are given), and each ",
+    "hit is a result of the rules whose patterns match the line. Cannot be combined with --invert-match."
+);
 const FILES_WITHOUT_MATCH_LONG : &str = "--files-without-match";
 const FILES_WITHOUT_MATCH_HELP : &str = concat!(
     "Suppress normal output, instead only print the names/paths of the files which do not contain ",
@@ -47,13 +107,159 @@ const COUNT_LONG : &str = "--count";
 const COUNT_HELP : &str = concat!(
     "Suppress normal output, instead print the number of matches for each input file."
 );
+const QUIET_HELP : &str = concat!(
+    "Do not print anything, and stop at the first match. The exit status is 0 if any line was ",
+    "selected, even if errors occurred."
+);
+const NO_MESSAGES_HELP : &str = concat!(
+    "Suppress the error messages about files that do not exist or cannot be read. ",
+    "Such files still result in exit status 2."
+);
+const EXTENDED_REGEXP_LONG : &str = "--extended-regexp";
+const EXTENDED_REGEXP_HELP : &str = concat!(
+    "Interpret the pattern as an extended regular expression instead of a glob-style pattern. ",
+    "This allows alternation (a|b), repetition counts (a{2,3}) and anchors (^, $)."
+);
+const FIXED_STRINGS_LONG : &str = "--fixed-strings";
+const FIXED_STRINGS_HELP : &str = concat!(
+    "Interpret the pattern as a literal string instead of a glob-style pattern, i.e. characters ",
+    "such as *, ? and [ only match themselves."
+);
+const IGNORE_CASE_HELP : &str = concat!(
+    "Ignore case distinctions in the pattern and the input, so that characters that only differ ",
+    "in case match each other. Case folding follows the Unicode rules, not just ASCII."
+);
+const NO_IGNORE_CASE_HELP : &str = concat!(
+    "Do not ignore case distinctions (the default). This cancels an earlier --ignore-case or ",
+    "--smart-case."
+);
+const SMART_CASE_HELP : &str = concat!(
+    "Ignore case distinctions if the pattern contains no uppercase letters, otherwise search ",
+    "case sensitively."
+);
+const REGEXP_HELP : &str = concat!(
+    "Search for the given pattern. May be given multiple times, lines matching any of the ",
+    "patterns are hits."
+);
+const PATTERN_FILE_HELP : &str = concat!(
+    "Read patterns from the given file, one per line. Use \"-\" to read them from standard input. ",
+    "May be given multiple times, and combined with --regexp."
+);
+const WORD_REGEXP_HELP : &str = concat!(
+    "Only select lines where the pattern matches a whole word, i.e. the match must be preceded ",
+    "and followed by a non-word character or the start or end of the line. Word characters are ",
+    "letters, digits and the underscore."
+);
+const LINE_REGEXP_HELP : &str = concat!(
+    "Only select lines where the pattern matches the whole line. This overrides --word-regexp."
+);
+const ONLY_MATCHING_HELP : &str = concat!(
+    "Print only the matched parts of a matching line, each on its own line (prefixed by the ",
+    "file name and line number, if these are printed)."
+);
+const MAX_COUNT_HELP : &str = concat!(
+    "Stop reading a file after NUM selected lines (non-matching lines, with --invert-match). ",
+    "Trailing context after the last of them is still printed, and --count counts at most NUM lines."
+);
+const MAX_TOTAL_HELP : &str = "Stop searching after NUM selected lines, summed over all files.";
+const AFTER_CONTEXT_HELP : &str = "Print NUM lines of trailing context after each hit.";
+const BEFORE_CONTEXT_HELP : &str = "Print NUM lines of leading context before each hit.";
+const CONTEXT_HELP : &str = concat!(
+    "Print NUM lines of context before and after each hit. --after-context and --before-context ",
+    "take precedence over this."
+);
+const GROUP_SEPARATOR_HELP : &str = concat!(
+    "When printing context lines, print SEP between groups of lines that are not adjacent ",
+    "(default: \"--\")."
+);
+const NO_GROUP_SEPARATOR_HELP : &str = "When printing context lines, do not separate groups of lines.";
+const COLOR_HELP : &str = concat!(
+    "Highlight matches, file names, line numbers and separators. WHEN is auto (the default, only ",
+    "color output to a terminal, unless NO_COLOR is set), always or never, and must be given as ",
+    "--color=WHEN. --color alone means auto, like in GNU grep. The colors can be ",
+    "configured with the GREP_COLORS environment variable, like in GNU grep."
+);
+const RECURSIVE_HELP : &str = concat!(
+    "Search the files in directories given as paths, recursively. Symbolic links are only ",
+    "followed if they are given as paths directly. Files are searched in order of their names."
+);
+const DEREFERENCE_RECURSIVE_HELP : &str = concat!(
+    "Like --recursive, but follow all symbolic links."
+);
+const NO_IGNORE_HELP : &str = concat!(
+    "When searching recursively, do not skip files and directories listed in ignore files ",
+    "(.gitignore, .git/info/exclude, the global gitignore, .ignore and .grepignore)."
+);
+const NO_IGNORE_VCS_HELP : &str = concat!(
+    "When searching recursively, do not skip files and directories listed in git's ignore files ",
+    "(.gitignore, .git/info/exclude and the global gitignore), but still respect .ignore and ",
+    ".grepignore."
+);
+const HIDDEN_HELP : &str = concat!(
+    "When searching recursively, also search hidden files and directories, i.e. those whose ",
+    "names start with a \".\"."
+);
+const INCLUDE_HELP : &str = concat!(
+    "Only search files whose name (or path) matches the given glob-style pattern. May be given ",
+    "multiple times. If --include and --exclude patterns contradict each other, the last matching ",
+    "one wins."
+);
+const EXCLUDE_HELP : &str = concat!(
+    "Skip files whose name (or path) matches the given glob-style pattern. May be given multiple ",
+    "times."
+);
+const EXCLUDE_DIR_HELP : &str = concat!(
+    "When searching recursively, skip directories whose name (or path) matches the given ",
+    "glob-style pattern. May be given multiple times."
+);
+const TYPE_HELP : &str = concat!(
+    "Only search files of the given type, e.g. \"rust\" for *.rs files. May be given multiple ",
+    "times. Use --type-list to show the known types."
+);
+const TYPE_NOT_HELP : &str = "Do not search files of the given type. May be given multiple times.";
+const TYPE_ADD_HELP : &str = concat!(
+    "Add a glob to a file type, given as NAME:GLOB, e.g. \"web:*.vue\". The type is created if it ",
+    "does not exist yet. May be given multiple times."
+);
+const TYPE_LIST_HELP : &str = "Print the known file types and their globs, then exit.";
+const BINARY_FILES_HELP : &str = concat!(
+    "How to treat binary files, i.e. files containing a NUL byte near their start: \"binary\" ",
+    "(the default) only reports whether they match, \"text\" searches them like text files and ",
+    "\"without-match\" treats them as if they did not match."
+);
+const TEXT_HELP : &str = "Search binary files like text files, same as --binary-files=text.";
+const IGNORE_BINARY_HELP : &str = "Treat binary files as if they did not match, same as --binary-files=without-match.";
+const ENCODING_HELP : &str = concat!(
+    "Transcode the input from the given encoding before searching it, e.g. \"latin1\", ",
+    "\"utf-16le\" or \"shift_jis\" (any WHATWG encoding label). The default, \"auto\", only ",
+    "transcodes input starting with a UTF-8 or UTF-16 byte order mark, which always takes precedence."
+);
+const SEARCH_ZIP_HELP : &str = concat!(
+    "Search inside files compressed with gzip, bzip2, xz or zstd, which are recognized by their ",
+    "contents (not by their extension). Hits are reported with the name of the compressed file."
+);
+const SEARCH_ARCHIVES_HELP : &str = concat!(
+    "Search the members of tar archives (.tar, possibly compressed, e.g. .tar.gz or .tgz) and zip ",
+    "archives (.zip), each like a file of its own. Hits are reported as \"ARCHIVE:MEMBER\", and ",
+    "--include, --exclude, --exclude-dir, --type and --type-not apply to the paths of the members ",
+    "within the archive. An archive itself is only skipped if an --exclude or --type-not matches it."
+);
+const PRE_HELP : &str = concat!(
+    "For each file, search the output of \"COMMAND FILE\" instead of the contents of the file, e.g. to ",
+    "search PDFs with a command that converts them to text. The file is also passed as the standard ",
+    "input of the command. Standard input (\"-\") is not preprocessed."
+);
+const PRE_GLOB_HELP : &str = concat!(
+    "Only preprocess the files matching this glob with --pre, others are searched as they are. ",
+    "May be given multiple times, files matching any of the globs are preprocessed."
+);
 const ABOUT_TEXT : &str = "search for strings (or patterns) in files";
 
 #[derive(Parser)]
 #[clap(about=ABOUT_TEXT)]
 struct Args {
     #[clap(help=PATTERN_HELP)]
-    pattern : String,
+    pattern : Option<String>,
     #[clap(help=FILES_HELP)]
     files: Vec<String>,
     #[clap(short='v', long, help=INVERT_MATCH_HELP)]
@@ -64,55 +270,323 @@ struct Args {
     force_no_print_filename: bool,
     #[clap(short='n', long=LINE_NUMBER_LONG, help=LINE_NUMBER_HELP)]
     print_line_number : bool,
+    #[clap(short='b', long, help=BYTE_OFFSET_HELP)]
+    byte_offset: bool,
+    #[clap(long, help=COLUMN_HELP)]
+    column: bool,
+    #[clap(long, help=VIMGREP_HELP)]
+    vimgrep: bool,
+    #[clap(long, help=JSON_HELP, conflicts_with="vimgrep")]
+    json: bool,
+    #[clap(long, help=SARIF_HELP, conflicts_with_all=&["vimgrep", "json", "invert-match"])]
+    sarif: bool,
     #[clap(short='l', long=FILES_WITH_MATCH_LONG, help=FILES_WITH_MATCH_HELP)]
     print_matching_files: bool,
     #[clap(short='L', long=FILES_WITHOUT_MATCH_LONG, help=FILES_WITHOUT_MATCH_HELP)]
     print_non_matching_files: bool,
     #[clap(short='c', long=COUNT_LONG, help=COUNT_HELP)]
     count_hits_per_file: bool,
+    #[clap(short='q', long, alias="silent", help=QUIET_HELP)]
+    quiet: bool,
+    #[clap(short='s', long, help=NO_MESSAGES_HELP)]
+    no_messages: bool,
+    #[clap(short='E', long=EXTENDED_REGEXP_LONG, help=EXTENDED_REGEXP_HELP)]
+    extended_regexp: bool,
+    #[clap(short='F', long=FIXED_STRINGS_LONG, help=FIXED_STRINGS_HELP)]
+    fixed_strings: bool,
+    #[clap(short='i', long, help=IGNORE_CASE_HELP, overrides_with_all=&["no-ignore-case", "smart-case"])]
+    ignore_case: bool,
+    #[clap(long, help=NO_IGNORE_CASE_HELP, overrides_with_all=&["ignore-case", "smart-case"])]
+    no_ignore_case: bool,
+    #[clap(short='S', long, help=SMART_CASE_HELP, overrides_with_all=&["ignore-case", "no-ignore-case"])]
+    smart_case: bool,
+    #[clap(short='e', long="regexp", value_name="PATTERN", help=REGEXP_HELP, number_of_values=1, allow_hyphen_values=true)]
+    patterns: Vec<String>,
+    #[clap(short='f', long="file", value_name="FILE", help=PATTERN_FILE_HELP, number_of_values=1)]
+    pattern_files: Vec<String>,
+    #[clap(short='w', long, help=WORD_REGEXP_HELP)]
+    word_regexp: bool,
+    #[clap(short='x', long, help=LINE_REGEXP_HELP)]
+    line_regexp: bool,
+    #[clap(short='o', long, help=ONLY_MATCHING_HELP)]
+    only_matching: bool,
+    #[clap(short='m', long, value_name="NUM", help=MAX_COUNT_HELP)]
+    max_count: Option<usize>,
+    #[clap(long, value_name="NUM", help=MAX_TOTAL_HELP)]
+    max_total: Option<usize>,
+    #[clap(short='A', long, value_name="NUM", help=AFTER_CONTEXT_HELP)]
+    after_context: Option<usize>,
+    #[clap(short='B', long, value_name="NUM", help=BEFORE_CONTEXT_HELP)]
+    before_context: Option<usize>,
+    #[clap(short='C', long, value_name="NUM", help=CONTEXT_HELP)]
+    context: Option<usize>,
+    #[clap(long, value_name="SEP", help=GROUP_SEPARATOR_HELP, allow_hyphen_values=true)]
+    group_separator: Option<String>,
+    #[clap(long, help=NO_GROUP_SEPARATOR_HELP, conflicts_with="group-separator")]
+    no_group_separator: bool,
+    #[clap(long, alias="colour", value_name="WHEN", default_value="auto", help=COLOR_HELP,
+           min_values=0, require_equals=true, default_missing_value="auto")]
+    color: ColorChoice,
+    #[clap(short='r', long, help=RECURSIVE_HELP)]
+    recursive: bool,
+    #[clap(short='R', long, help=DEREFERENCE_RECURSIVE_HELP)]
+    dereference_recursive: bool,
+    #[clap(long, help=NO_IGNORE_HELP)]
+    no_ignore: bool,
+    #[clap(long, help=NO_IGNORE_VCS_HELP)]
+    no_ignore_vcs: bool,
+    #[clap(long, help=HIDDEN_HELP)]
+    hidden: bool,
+    #[clap(long, value_name="GLOB", help=INCLUDE_HELP, number_of_values=1)]
+    include: Vec<String>,
+    #[clap(long, value_name="GLOB", help=EXCLUDE_HELP, number_of_values=1)]
+    exclude: Vec<String>,
+    #[clap(long, value_name="GLOB", help=EXCLUDE_DIR_HELP, number_of_values=1)]
+    exclude_dir: Vec<String>,
+    #[clap(short='t', long="type", value_name="TYPE", help=TYPE_HELP, number_of_values=1)]
+    file_types: Vec<String>,
+    #[clap(short='T', long="type-not", value_name="TYPE", help=TYPE_NOT_HELP, number_of_values=1)]
+    file_types_not: Vec<String>,
+    #[clap(long, value_name="NAME:GLOB", help=TYPE_ADD_HELP, number_of_values=1)]
+    type_add: Vec<String>,
+    #[clap(long, help=TYPE_LIST_HELP)]
+    type_list: bool,
+    #[clap(long, value_name="TYPE", default_value="binary", help=BINARY_FILES_HELP)]
+    binary_files: BinaryFiles,
+    #[clap(short='a', long, help=TEXT_HELP, overrides_with="ignore-binary")]
+    text: bool,
+    #[clap(short='I', help=IGNORE_BINARY_HELP, overrides_with="text")]
+    ignore_binary: bool,
+    #[clap(long, value_name="LABEL", default_value="auto", help=ENCODING_HELP)]
+    encoding: String,
+    #[clap(short='z', long, help=SEARCH_ZIP_HELP)]
+    search_zip: bool,
+    #[clap(long, help=SEARCH_ARCHIVES_HELP)]
+    search_archives: bool,
+    #[clap(long, value_name="COMMAND", help=PRE_HELP)]
+    pre: Option<String>,
+    #[clap(long, value_name="GLOB", help=PRE_GLOB_HELP, number_of_values=1, requires="pre")]
+    pre_glob: Vec<String>,
+}
+
+// builds the path filter from --include, --exclude, --exclude-dir, --type and --type-not,
+// keeping the order in which the options were given on the command line. A file type
+// behaves like an --include (or --exclude) of each of its globs.
+fn path_filter(args: &Args, arg_matches: &clap::ArgMatches, file_types: &FileTypes) -> Result<PathFilter, String> {
+    let mut rules: Vec<(usize, FilterKind, &str)> = Vec::new();
+    for (id, kind, globs) in [
+        ("include", FilterKind::Include, &args.include),
+        ("exclude", FilterKind::Exclude, &args.exclude),
+        ("exclude-dir", FilterKind::ExcludeDir, &args.exclude_dir),
+    ] {
+        if let Some(indices) = arg_matches.indices_of(id) {
+            rules.extend(indices.zip(globs).map(|(index, glob)| (index, kind, &glob[..])));
+        }
+    }
+    for (id, kind, names) in [
+        ("file-types", FilterKind::Include, &args.file_types),
+        ("file-types-not", FilterKind::Exclude, &args.file_types_not),
+    ] {
+        if let Some(indices) = arg_matches.indices_of(id) {
+            for (index, name) in indices.zip(names) {
+                rules.extend(file_types.globs(name)?.iter().map(|glob| (index, kind, &glob[..])));
+            }
+        }
+    }
+    // the sort is stable, so the globs of a type stay in order
+    rules.sort_by_key(|(index, _kind, _glob)| *index);
+
+    let mut path_filter = PathFilter::new();
+    for (_index, kind, glob) in rules {
+        path_filter.add(kind, glob)?;
+    }
+    Ok(path_filter)
+}
+
+// reads one pattern per line from the given file, or from standard input for "-"
+fn read_patterns(path: &str) -> std::io::Result<Vec<String>> {
+    let reader: Box<dyn BufRead> = match path {
+        "-" => Box::new(BufReader::new(std::io::stdin())),
+        _ => Box::new(BufReader::new(File::open(path)?)),
+    };
+    reader.lines().collect()
+}
+
+// the maximum number of lines to select from the next input, given --max-count and the number of
+// lines that may still be selected in total
+fn max_count(max_count: Option<usize>, remaining_total: Option<usize>) -> Option<usize> {
+    match (max_count, remaining_total) {
+        (Some(max_count), Some(remaining_total)) => Some(max_count.min(remaining_total)),
+        _ => max_count.or(remaining_total),
+    }
+}
+
+// archive members are filtered by their path within the archive. Like in a directory walk, a
+// member is also skipped if any of its parent directories is excluded.
+fn includes_member(path_filter: &PathFilter, member_path: &str) -> bool {
+    let mut parents = Path::new(member_path).ancestors().skip(1);
+    path_filter.includes_file(member_path)
+        && parents.all(|dir| dir.as_os_str().is_empty() || path_filter.includes_dir(&dir.to_string_lossy()))
+}
+
+// the exit status, as defined by POSIX
+#[derive(Clone, Copy)]
+enum ExitStatus {
+    Match = 0,
+    NoMatch = 1,
+    Error = 2,
 }
 
 fn main() {
+    let status = match run() {
+        Ok(status) => status,
+        // the reader of the output is gone (e.g. `grep ... | head -1`), which is not worth a message
+        Err(Error::Output(error)) if error.kind() == io::ErrorKind::BrokenPipe => ExitStatus::Match,
+        Err(error) => {
+            errors::report(&error);
+            ExitStatus::Error
+        }
+    };
+    std::process::exit(status as i32);
+}
+
+// errors that prevent searching altogether are returned, errors concerning single files are
+// reported right away, and the remaining files are still searched
+fn run() -> Result<ExitStatus, Error> {
 
     // Argument parsing and sanity checking, setup
 
-    let mut args = Args::parse();
+    // parsed in two steps, since the filters need the positions of the options
+    let arg_matches = Args::command().get_matches();
+    let mut args = match Args::from_arg_matches(&arg_matches) {
+        Ok(args) => args,
+        Err(error) => error.exit(),
+    };
+
+    let mut file_types = FileTypes::new();
+    for definition in &args.type_add {
+        file_types.add(definition).map_err(Error::Usage)?;
+    }
+    if args.type_list {
+        let mut stdout = io::stdout().lock();
+        for (name, globs) in file_types.iter() {
+            writeln!(stdout, "{}: {}", name, globs.join(", ")).map_err(Error::Output)?;
+        }
+        return Ok(ExitStatus::Match)
+    }
 
-    if args.files.is_empty() {
+    let mut patterns = args.patterns.clone();
+    for pattern_file in &args.pattern_files {
+        let file_patterns = read_patterns(pattern_file).map_err(|error| Error::io(pattern_file, error))?;
+        patterns.extend(file_patterns);
+    }
+    if args.patterns.is_empty() && args.pattern_files.is_empty() {
+        match args.pattern.take() {
+            Some(pattern) => patterns.push(pattern),
+            None => return Err(Error::Usage(String::from("no pattern given"))),
+        }
+    } else if let Some(file) = args.pattern.take() {
+        // with -e or -f, the first positional argument is a file rather than the pattern
+        args.files.insert(0, file);
+    }
+
+    let recursive = args.recursive || args.dereference_recursive;
+    let search_current_dir = recursive && args.files.is_empty();
+    if search_current_dir {
+        args.files.push(String::from("."));
+    } else if args.files.is_empty() {
         args.files.push(String::from("-"));
     }
 
-    let pattern = glob::ParsedGlobString::try_from(&args.pattern[..]);
-    if pattern.is_err() {
-        //FIXME: better error handling
-        println!("could not parse pattern: {:?}", pattern.unwrap_err());
-        return
+    let path_filter = path_filter(&args, &arg_matches, &file_types)
+        .map_err(|error| Error::Usage(format!("could not parse file filter: {}", error)))?;
+
+    let walk_options = WalkOptions {
+        follow_symlinks: args.dereference_recursive,
+        hidden: args.hidden,
+        ignore_files: !args.no_ignore,
+        ignore_vcs: !args.no_ignore_vcs,
+        path_filter,
+        search_archives: args.search_archives,
+    };
+    if args.extended_regexp && args.fixed_strings {
+        return Err(Error::Usage(format!("conflicting command line options: {} and {} (or equivalents)",
+                                        EXTENDED_REGEXP_LONG, FIXED_STRINGS_LONG)));
+    }
+
+    let syntax = match (args.extended_regexp, args.fixed_strings) {
+        (true, _) => PatternSyntax::ExtendedRegex,
+        (false, true) => PatternSyntax::FixedString,
+        (false, false) => PatternSyntax::Glob,
+    };
+
+    // at most one of the three case options is set, since the last one given overrides the others
+    let case_insensitive = !args.no_ignore_case && (args.ignore_case
+        || (args.smart_case && !patterns.iter().any(|pattern| matching::has_uppercase_literal(pattern, syntax))));
+
+    let matcher_options = MatcherOptions {
+        syntax,
+        case_insensitive,
+        whole_words: args.word_regexp,
+        whole_lines: args.line_regexp,
+    };
+
+    let matcher = matching::new_multi_matcher(&patterns, &matcher_options)
+        .map_err(|error| Error::Usage(format!("could not parse pattern: {}", error)))?;
+
+    let encoding = input::encoding_for_label(&args.encoding).map_err(Error::Usage)?;
+
+    // with no --pre-glob, all files are preprocessed
+    let mut pre_filter = PathFilter::new();
+    for glob in &args.pre_glob {
+        pre_filter.add(FilterKind::Include, glob)
+            .map_err(|error| Error::Usage(format!("invalid glob {}: {}", glob, error)))?;
     }
-    let pattern = pattern.unwrap();
 
     let mut normal_output = true;
-    let mut print_files = args.files.len() > 1;
+    let mut print_files = args.files.iter()
+            .filter(|path| *path == "-" || walk_options.includes_file(path))
+            .count() > 1
+        || (recursive && args.files.iter().any(|path| path != "-" && Path::new(path).is_dir()))
+        // the members are printed as "ARCHIVE:MEMBER", even for a single archive
+        || (args.search_archives && args.files.iter().any(|path| {
+            ArchiveKind::from_path(path).is_some() && !(args.pre.is_some() && pre_filter.includes_file(path))
+        }));
     let print_lines = args.print_line_number;
     let print_hit = true;
     let mut skip_file_after_first_match = false;
+    let binary_files = match (args.text, args.ignore_binary) {
+        (true, _) => BinaryFiles::Text,
+        (false, true) => BinaryFiles::WithoutMatch,
+        (false, false) => args.binary_files,
+    };
+    let after_context = args.after_context.or(args.context).unwrap_or(0);
+    let before_context = args.before_context.or(args.context).unwrap_or(0);
 
     match (args.force_print_filename, args.force_no_print_filename) {
         (true, true) => {
-            //FIXME: better error handling
-            println!("conflicting command line options: {} and {} (or equivalents)", PRINT_FILENAME, NO_PRINT_FILENAME);
-            return
+            return Err(Error::Usage(format!("conflicting command line options: {} and {} (or equivalents)",
+                                            PRINT_FILENAME, NO_PRINT_FILENAME)));
         }
         (false, true) => { print_files = false; }
         (true, false) => { print_files = true; }
         (false, false) => { }
     }
 
+    // --quiet overrides the other output options
+    if args.quiet {
+        args.print_matching_files = false;
+        args.print_non_matching_files = false;
+        args.count_hits_per_file = false;
+        normal_output = false;
+        skip_file_after_first_match = true;
+    }
+
     match (args.print_matching_files, args.print_non_matching_files, args.count_hits_per_file) {
         (true, true, _) | (true, _, true) | (_, true, true) => {
-            //FIXME: better error handling
-            println!("conflicting command line options: only one of {}, {} and {} (or equivalents) may be given",
-                     FILES_WITH_MATCH_LONG, FILES_WITHOUT_MATCH_LONG, COUNT_LONG);
-            return;
+            return Err(Error::Usage(format!(
+                "conflicting command line options: only one of {}, {} and {} (or equivalents) may be given",
+                FILES_WITH_MATCH_LONG, FILES_WITHOUT_MATCH_LONG, COUNT_LONG)));
         }
         (true, false, false) => {
             normal_output = false;
@@ -132,81 +606,219 @@ fn main() {
         false => None
     };
 
-    let mut hit_printer : Option<HitPrinter> = match normal_output {
-        true => Some(HitPrinter::new(print_files, print_lines, print_hit)),
+    let mut hit_printer : Option<HitPrinter> = match normal_output && !args.vimgrep && !args.json && !args.sarif {
+        true => {
+            let group_separator = match (after_context > 0 || before_context > 0, args.no_group_separator) {
+                (true, false) => Some(args.group_separator.clone().unwrap_or_else(|| String::from("--"))),
+                _ => None,
+            };
+            let colors = match args.color.use_colors() {
+                true => Some(Colors::from_env()),
+                false => None,
+            };
+            Some(HitPrinter::new(print_files, print_lines, print_hit, args.only_matching)
+                .with_group_separator(group_separator)
+                .with_byte_offset(args.byte_offset)
+                .with_column(args.column)
+                .with_colors(colors))
+        }
         false => None,
     };
 
+    let mut vimgrep_printer = match normal_output && args.vimgrep {
+        true => Some(VimgrepPrinter::new(args.only_matching)),
+        false => None,
+    };
+
+    let mut json_printer = match normal_output && args.json {
+        true => Some(JsonPrinter::new(io::stdout())),
+        false => None,
+    };
+
+    let mut sarif_printer = match normal_output && args.sarif {
+        true => {
+            // each pattern is matched on its own, to find the rules a hit belongs to
+            let mut rules = Vec::new();
+            for pattern in &patterns {
+                let matcher = matching::new_matcher(pattern, &matcher_options)
+                    .map_err(|error| Error::Usage(format!("could not parse pattern: {}", error)))?;
+                rules.push((pattern.clone(), matcher));
+            }
+            Some(SarifPrinter::new(rules))
+        }
+        false => None,
+    };
+
+    // the files are only walked once everything else has been checked, and only as far as needed
+    let files : Box<dyn Iterator<Item = Result<PathBuf, Error>>> = match recursive {
+        // like GNU grep, list files in the implicitly searched directory without "./"
+        true => Box::new(walking::walk(&args.files, &walk_options)
+            .map(|file| file.map(|file| match search_current_dir {
+                true => file.strip_prefix(".").map(Path::to_path_buf).unwrap_or(file),
+                false => file,
+            }))),
+        false => Box::new(args.files.iter()
+            .filter(|path| *path == "-" || walk_options.includes_file(path))
+            .map(|path| Ok(PathBuf::from(path)))),
+    };
+    // set if any file or directory could not be searched
+    let mut had_errors = false;
+    let mut selected_lines = 0;
+
     {
-        let mut hit_handlers : Vec<Box<&mut dyn HitHandler>> = Vec::new();
-        hit_printer.as_mut().and_then(|mut_ref| Some(hit_handlers.push(Box::new(mut_ref))));
-        hit_counter.as_mut().and_then(|mut_ref| Some(hit_handlers.push(Box::new(mut_ref))));
+        let mut hit_handlers : Vec<&mut dyn HitHandler> = Vec::new();
+        hit_printer.as_mut().and_then(|mut_ref| Some(hit_handlers.push(mut_ref)));
+        vimgrep_printer.as_mut().and_then(|mut_ref| Some(hit_handlers.push(mut_ref)));
+        json_printer.as_mut().and_then(|mut_ref| Some(hit_handlers.push(mut_ref)));
+        sarif_printer.as_mut().and_then(|mut_ref| Some(hit_handlers.push(mut_ref)));
+        hit_counter.as_mut().and_then(|mut_ref| Some(hit_handlers.push(mut_ref)));
 
         // search the input files
 
+        // with --quiet, the exit status is known after the first match
+        let max_total = match args.quiet {
+            true => Some(1),
+            false => args.max_total,
+        };
+        let search_options = SearchOptions {
+            invert_match: args.invert_match,
+            before_context,
+            after_context,
+            binary_files,
+            // a summary line would not be valid JSON, nor a record for editors
+            summarize_binary_files: normal_output && !args.json && !args.sarif && !args.vimgrep,
+            skip_file_after_first_match,
+            max_count: args.max_count,
+            search_zip: args.search_zip,
+            encoding,
+        };
+
+        // searches a single operand, returning the number of selected lines. The path is only
+        // converted to text (lossily) for printing it.
         let stdin = std::io::stdin();
-        for file_path in &args.files {
-            let reader: Box<dyn BufRead>;
-            match &file_path[..] {
+        let mut search_file = |path: &Path, remaining_total: Option<usize>| -> Result<usize, Error> {
+            let options = SearchOptions {
+                max_count: max_count(search_options.max_count, remaining_total),
+                ..search_options
+            };
+            let file_path = path.to_string_lossy();
+            let file_path = &*file_path;
+            let file = match file_path {
                 "-" => {
-                    reader = Box::new(stdin.lock());
-                    //reader.lock();
+                    let source = Box::new(stdin.lock());
+                    return searching::search(source, file_path, &*matcher, &options, &mut hit_handlers)
                 }
-                _ => {
-                    let file = File::options().read(true).open(&file_path);
-                    if file.is_err() {
-                        //FIXME: better error handling
-                        println!("could not open file {}", file_path);
-                        return
-                    }
-                    let file = file.unwrap();
-                    reader = Box::new(BufReader::new(file));
+                // without --recursive, directories are skipped (like GNU grep does)
+                _ if path.is_dir() => {
+                    return Err(Error::io(file_path, io::Error::from(io::ErrorKind::IsADirectory)))
                 }
+                _ => File::options().read(true).open(path).map_err(|error| Error::io(file_path, error))?,
+            };
+            // preprocessed files are searched as they are output by the command, even archives
+            let pre_command = args.pre.as_deref().filter(|_| pre_filter.includes_file(file_path));
+            let archive_kind = match args.search_archives && pre_command.is_none() {
+                true => ArchiveKind::from_path(file_path),
+                false => None,
+            };
+            if let Some(kind) = archive_kind {
+                let mut selected_lines = 0;
+                archives::for_each_member(file, file_path, kind, |member_path, member| {
+                    let remaining_total = remaining_total.map(|remaining_total| remaining_total - selected_lines);
+                    if !includes_member(&walk_options.path_filter, member_path) || remaining_total == Some(0) {
+                        return Ok(())
+                    }
+                    let options = SearchOptions {
+                        max_count: max_count(search_options.max_count, remaining_total),
+                        ..search_options
+                    };
+                    let member_path = format!("{}:{}", file_path, member_path);
+                    selected_lines += searching::search(member, &member_path, &*matcher, &options, &mut hit_handlers)?;
+                    Ok(())
+                })?;
+                return Ok(selected_lines)
             }
+            let source = match pre_command {
+                Some(command) => input::preprocessed_reader(command, path, file)
+                    .map_err(|error| Error::io(file_path, error))?,
+                None => Box::new(file),
+            };
+            searching::search(source, file_path, &*matcher, &options, &mut hit_handlers)
+        };
 
-            for hit_handler in hit_handlers.iter_mut() {
-                hit_handler.start_new_file(file_path);
-            }
-
-            for (line_no, line) in reader.lines().enumerate() {
-                let line = line.unwrap();
-                let mut matches = pattern.matches_partially(&line);
-                if args.invert_match {
-                    matches = !matches;
-                }
-                if matches {
-                    for hit_handler in hit_handlers.iter_mut() {
-                        hit_handler.handle_hit(file_path, line_no + 1, &line);
-                    }
-                    if skip_file_after_first_match {
-                        break
+        for file_path in files {
+            let remaining_total = max_total.map(|max_total| max_total - selected_lines);
+            match file_path.and_then(|file_path| search_file(&file_path, remaining_total)) {
+                Ok(lines) => selected_lines += lines,
+                // the remaining files are not searched if the output cannot be written
+                Err(error @ Error::Output(_)) => return Err(error),
+                Err(error) => {
+                    if !args.no_messages {
+                        errors::report(&error);
                     }
+                    had_errors = true;
                 }
             }
-
+            if max_total.is_some_and(|max_total| selected_lines >= max_total) {
+                break
+            }
         } // end of lifetime of hit_handlers, we now have full ownership of the hit handlers again
 
+        if let Some(json_printer) = json_printer.as_mut() {
+            json_printer.print_summary().map_err(Error::Output)?;
+        }
+
+        if let Some(sarif_printer) = sarif_printer.as_ref() {
+            sarif_printer.print_log().map_err(Error::Output)?;
+        }
+
+        let mut stdout = io::stdout().lock();
         if args.print_matching_files {
             let hit_counter = hit_counter.as_ref().unwrap();
             for (file, _count) in hit_counter.iter().filter(|(_file, count)| *count > 0) {
-                println!("{}", file);
+                writeln!(stdout, "{}", file).map_err(Error::Output)?;
             }
         };
 
         if args.print_non_matching_files {
             let hit_counter = hit_counter.as_ref().unwrap();
             for (file, _count) in hit_counter.iter().filter(|(_file, count)| *count == 0) {
-               println!("{}", file);
+               writeln!(stdout, "{}", file).map_err(Error::Output)?;
             }
         };
 
         if args.count_hits_per_file {
             let hit_counter = hit_counter.as_ref().unwrap();
             for (file, count) in hit_counter {
-                println!("{}:{}", file, count);
+                writeln!(stdout, "{}:{}", file, count).map_err(Error::Output)?;
             }
         };
 
     }
 
+    // with --files-without-match, success means that a file was listed (like in GNU grep)
+    let matched = match (args.print_non_matching_files, &hit_counter) {
+        (true, Some(hit_counter)) => hit_counter.iter().any(|(_file, count)| count == 0),
+        _ => selected_lines > 0,
+    };
+    // like POSIX requires, a match found with --quiet takes precedence over errors
+    Ok(match (had_errors && !(args.quiet && matched), matched) {
+        (true, _) => ExitStatus::Error,
+        (false, true) => ExitStatus::Match,
+        (false, false) => ExitStatus::NoMatch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_count_is_limited_by_the_remaining_total() {
+        assert_eq!(max_count(None, None), None);
+        assert_eq!(max_count(Some(3), None), Some(3));
+        assert_eq!(max_count(None, Some(2)), Some(2));
+        assert_eq!(max_count(Some(3), Some(2)), Some(2));
+        assert_eq!(max_count(Some(1), Some(2)), Some(1));
+        assert_eq!(max_count(Some(3), Some(0)), Some(0));
+    }
 }
@@ -0,0 +1,480 @@
+// common interface of the different pattern syntaxes, so that the search loop
+// does not need to know which kind of pattern it is dealing with
+pub trait Matcher {
+    // returns the byte offsets (start, end) of the leftmost match in the line that starts
+    // at or after the given offset
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)>;
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    // returns the byte offsets of all non-overlapping matches in the line, from left to right
+    fn find_iter(&self, line: &[u8]) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while let Some((match_start, match_end)) = self.find_at(line, start) {
+            matches.push((match_start, match_end));
+            start = match (match_end > match_start, match_end < line.len()) {
+                (true, _) => match_end,
+                // an empty match would be found again at the same position, skip one character
+                // (or byte, if it is not valid UTF-8)
+                (false, true) => match_end + char_after(line, match_end).map_or(1, |(_c, length)| length),
+                (false, false) => break,
+            };
+        }
+        matches
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PatternSyntax {
+    Glob,
+    ExtendedRegex,
+    FixedString,
+}
+
+#[derive(Clone, Copy)]
+pub struct MatcherOptions {
+    pub syntax: PatternSyntax,
+    pub case_insensitive: bool,
+    // the match must be preceded and followed by a non-word character (or the line boundary)
+    pub whole_words: bool,
+    // the match must span the whole line
+    pub whole_lines: bool,
+}
+
+// builds the matcher for a single pattern given on the command line
+pub fn new_matcher(pattern: &str, options: &MatcherOptions) -> Result<Box<dyn Matcher>, String> {
+    let matcher : Box<dyn Matcher> = match options.syntax {
+        PatternSyntax::Glob => Box::new(GlobMatcher::new(pattern, options)?),
+        PatternSyntax::ExtendedRegex => Box::new(RegexMatcher::new(&match_invalid_utf8(pattern), options)?),
+        PatternSyntax::FixedString if !options.case_insensitive && !options.whole_lines => {
+            Box::new(FixedStringMatcher::new(pattern))
+        }
+        // memchr only compares bytes, so case folding and anchoring of literals is left to
+        // the regex engine
+        PatternSyntax::FixedString => Box::new(RegexMatcher::new(&regex::escape(pattern), options)?),
+    };
+    Ok(restrict_to_words(matcher, options))
+}
+
+// builds a matcher that matches wherever at least one of the given patterns matches
+pub fn new_multi_matcher(patterns: &[String], options: &MatcherOptions) -> Result<Box<dyn Matcher>, String> {
+    if patterns.len() == 1 {
+        return new_matcher(&patterns[0], options);
+    }
+
+    // Patterns without metacharacters are collected into a single automaton, so that long
+    // lists of literals do not need one pass over the line per pattern.
+    let (literals, patterns): (Vec<&String>, Vec<&String>) = patterns.iter()
+        .partition(|pattern| is_literal(pattern, options.syntax));
+
+    let literal_options = MatcherOptions { syntax: PatternSyntax::FixedString, ..*options };
+    let mut matchers = Vec::new();
+    match (literals.len(), options.case_insensitive || options.whole_lines) {
+        (0, _) => { /* NOP */ }
+        (1, _) => matchers.push(new_matcher(literals[0], &literal_options)?),
+        (_, false) => {
+            let matcher = Box::new(LiteralSetMatcher::new(&literals)?);
+            matchers.push(restrict_to_words(matcher, options));
+        }
+        // the automaton only supports ASCII case folding and no anchors, the regex engine does both
+        (_, true) => {
+            let alternation: Vec<String> = literals.iter().map(|literal| regex::escape(literal)).collect();
+            let matcher = Box::new(RegexMatcher::new(&alternation.join("|"), options)?);
+            matchers.push(restrict_to_words(matcher, options));
+        }
+    }
+    for pattern in patterns {
+        matchers.push(new_matcher(pattern, options)?);
+    }
+
+    match matchers.len() {
+        1 => Ok(matchers.pop().unwrap()),
+        _ => Ok(Box::new(AnyMatcher { matchers })),
+    }
+}
+
+fn is_literal(pattern: &str, syntax: PatternSyntax) -> bool {
+    match syntax {
+        PatternSyntax::FixedString => true,
+        PatternSyntax::Glob => !pattern.contains(['*', '?', '[', '\\']),
+        // regex::escape escapes exactly the characters that have a special meaning
+        PatternSyntax::ExtendedRegex => regex::escape(pattern) == pattern,
+    }
+}
+
+// -x implies -w, so words are only checked if whole lines are not requested
+fn restrict_to_words(matcher: Box<dyn Matcher>, options: &MatcherOptions) -> Box<dyn Matcher> {
+    match options.whole_words && !options.whole_lines {
+        true => Box::new(WordMatcher { matcher }),
+        false => matcher,
+    }
+}
+
+// decides whether smart case should search case sensitively, i.e. whether the pattern
+// contains an uppercase letter that is meant literally (and is not part of an escape
+// sequence like \W in a regular expression)
+pub fn has_uppercase_literal(pattern: &str, syntax: PatternSyntax) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' && syntax == PatternSyntax::ExtendedRegex {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+// Glob patterns are validated by the glob library, but matched by translating them into
+// an equivalent regular expression, which gives us Unicode case folding and match
+// boundaries for free.
+pub struct GlobMatcher {
+    matcher: RegexMatcher,
+}
+
+impl GlobMatcher {
+    pub fn new(pattern: &str, options: &MatcherOptions) -> Result<Self, String> {
+        if let Err(error) = glob::ParsedGlobString::try_from(pattern) {
+            return Err(format!("{:?}", error));
+        }
+        Ok(GlobMatcher { matcher: RegexMatcher::new(&glob_to_regex(pattern), options)? })
+    }
+}
+
+impl Matcher for GlobMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        self.matcher.find_at(line, start)
+    }
+}
+
+// Matches any character, or a single byte that is not part of valid UTF-8 (e.g. in Latin-1
+// text), which "." alone does not match in Unicode mode.
+const ANY_CHAR : &str = r"(?:.|(?-u:[\x80-\xFF]))";
+const INVALID_BYTE : &str = r"(?-u:[\x80-\xFF])";
+
+// translates the glob syntax (*, ?, [...], [!...] and backslash escapes) into an unanchored
+// regular expression
+fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::new();
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                regex.push_str(ANY_CHAR);
+                regex.push('*');
+            }
+            '?' => regex.push_str(ANY_CHAR),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                push_escaped(&mut regex, chars[i]);
+            }
+            '[' => match chars[i + 1..].iter().skip(1).position(|c| *c == ']') {
+                Some(offset) => {
+                    let end = i + 2 + offset;
+                    let mut class = &chars[i + 1..end];
+                    let negated = matches!(class.first(), Some('!' | '^'));
+                    // a negated class also matches bytes that are not valid UTF-8
+                    if negated {
+                        regex.push_str("(?:[^");
+                        class = &class[1..];
+                    } else {
+                        regex.push('[');
+                    }
+                    for c in class {
+                        match c {
+                            '-' => regex.push('-'),
+                            _ => push_escaped(&mut regex, *c),
+                        }
+                    }
+                    regex.push(']');
+                    if negated {
+                        regex.push('|');
+                        regex.push_str(INVALID_BYTE);
+                        regex.push(')');
+                    }
+                    i = end;
+                }
+                None => push_escaped(&mut regex, '['),
+            },
+            c => push_escaped(&mut regex, c),
+        }
+        i += 1;
+    }
+    regex
+}
+
+// rewrites "." and negated classes of an extended regular expression, so that they also
+// match bytes that are not valid UTF-8, like their glob counterparts do
+fn match_invalid_utf8(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                regex.extend(&chars[i..(i + 2).min(chars.len())]);
+                i += 1;
+            }
+            '.' => regex.push_str(ANY_CHAR),
+            '[' => match class_end(&chars, i) {
+                Some(end) => {
+                    let class: String = chars[i..=end].iter().collect();
+                    match chars.get(i + 1) {
+                        Some('^') => regex.push_str(&format!("(?:{}|{})", class, INVALID_BYTE)),
+                        _ => regex.push_str(&class),
+                    }
+                    i = end;
+                }
+                // invalid, left for the regex parser to report
+                None => regex.push('['),
+            },
+            c => regex.push(c),
+        }
+        i += 1;
+    }
+    regex
+}
+
+// returns the index of the "]" closing the character class that starts at `start`, taking
+// escapes, nested classes (like [[:alpha:]]) and a leading literal "]" into account
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if chars.get(i) == Some(&'^') {
+        i += 1;
+    }
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => i = class_end(chars, i)?,
+            ']' => return Some(i),
+            _ => { /* NOP */ }
+        }
+        i += 1;
+    }
+    None
+}
+
+fn push_escaped(regex: &mut String, c: char) {
+    if c.is_ascii_punctuation() {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+pub struct RegexMatcher {
+    regex: regex::bytes::Regex,
+}
+
+impl RegexMatcher {
+    pub fn new(pattern: &str, options: &MatcherOptions) -> Result<Self, String> {
+        let pattern = match options.whole_lines {
+            true => format!("^(?:{})$", pattern),
+            false => pattern.to_string(),
+        };
+        let regex = regex::bytes::RegexBuilder::new(&pattern)
+            .case_insensitive(options.case_insensitive)
+            .build()
+            .map_err(|error| error.to_string())?;
+        Ok(RegexMatcher { regex })
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        self.regex.find_at(line, start).map(|m| (m.start(), m.end()))
+    }
+}
+
+// searches for the pattern as a literal string, i.e. without interpreting any
+// metacharacters, using memchr's substring search (Two-Way with a SIMD prefilter)
+pub struct FixedStringMatcher {
+    finder: memchr::memmem::Finder<'static>,
+}
+
+impl FixedStringMatcher {
+    pub fn new(pattern: &str) -> Self {
+        FixedStringMatcher { finder: memchr::memmem::Finder::new(pattern).into_owned() }
+    }
+}
+
+impl Matcher for FixedStringMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        let offset = self.finder.find(&line[start..])?;
+        Some((start + offset, start + offset + self.finder.needle().len()))
+    }
+}
+
+// searches for many literal strings at once, using an Aho-Corasick automaton
+pub struct LiteralSetMatcher {
+    automaton: aho_corasick::AhoCorasick,
+}
+
+impl LiteralSetMatcher {
+    pub fn new<S: AsRef<str>>(literals: &[S]) -> Result<Self, String> {
+        // preferring the longest literal at a position keeps -w from rejecting "foobar"
+        // just because "foo" is also a pattern
+        let automaton = aho_corasick::AhoCorasick::builder()
+            .match_kind(aho_corasick::MatchKind::LeftmostLongest)
+            .build(literals.iter().map(|literal| literal.as_ref()))
+            .map_err(|error| error.to_string())?;
+        Ok(LiteralSetMatcher { automaton })
+    }
+}
+
+impl Matcher for LiteralSetMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        let input = aho_corasick::Input::new(line).span(start..line.len());
+        self.automaton.find(input).map(|m| (m.start(), m.end()))
+    }
+}
+
+// matches wherever at least one of its matchers matches
+pub struct AnyMatcher {
+    matchers: Vec<Box<dyn Matcher>>,
+}
+
+impl Matcher for AnyMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        // leftmost match wins, on ties the longer one
+        self.matchers.iter()
+            .filter_map(|matcher| matcher.find_at(line, start))
+            .min_by_key(|(start, end)| (*start, std::cmp::Reverse(*end)))
+    }
+}
+
+// only accepts matches that are neither preceded nor followed by a word character
+pub struct WordMatcher {
+    matcher: Box<dyn Matcher>,
+}
+
+impl Matcher for WordMatcher {
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        let mut start = start;
+        while let Some((match_start, match_end)) = self.matcher.find_at(line, start) {
+            let word_before = char_before(line, match_start).is_some_and(|(c, _length)| is_word_char(c));
+            let word_after = char_after(line, match_end).is_some_and(|(c, _length)| is_word_char(c));
+            if !word_before && !word_after {
+                return Some((match_start, match_end));
+            }
+            // a later occurrence might still be a whole word, so retry after the first
+            // character of the rejected match
+            start = match_start + char_after(line, match_start).map_or(1, |(_c, length)| length);
+            if start > line.len() {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Lines are not necessarily valid UTF-8, so these decode the character ending (or starting)
+// at the given offset, if there is a valid one, together with its length in bytes.
+fn char_before(line: &[u8], offset: usize) -> Option<(char, usize)> {
+    (1..=offset.min(4)).find_map(|length| decode(&line[offset - length..offset]))
+}
+
+fn char_after(line: &[u8], offset: usize) -> Option<(char, usize)> {
+    (1..=(line.len() - offset).min(4)).find_map(|length| decode(&line[offset..offset + length]))
+}
+
+fn decode(bytes: &[u8]) -> Option<(char, usize)> {
+    let c = std::str::from_utf8(bytes).ok()?.chars().next()?;
+    Some((c, bytes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str, syntax: PatternSyntax) -> Box<dyn Matcher> {
+        let options = MatcherOptions { syntax, case_insensitive: false, whole_words: false, whole_lines: false };
+        new_matcher(pattern, &options).unwrap()
+    }
+
+    fn word_matcher(pattern: &str) -> Box<dyn Matcher> {
+        let options = MatcherOptions {
+            syntax: PatternSyntax::FixedString,
+            case_insensitive: false,
+            whole_words: true,
+            whole_lines: false,
+        };
+        new_matcher(pattern, &options).unwrap()
+    }
+
+    #[test]
+    fn glob_matches_like_the_glob_library() {
+        let patterns = ["foo", "f*o", "f?o", "*", "[abc]x", "[!a]b", "[a-c]z", r"\*x", r"a\?"];
+        let lines = ["foo", "fxo", "xfoo bar", "bx", "ab", "cb", "bz", "dz", "*x", "yx", "a?", "ba"];
+        for pattern in patterns {
+            let glob = glob::ParsedGlobString::try_from(pattern).unwrap();
+            let matcher = matcher(pattern, PatternSyntax::Glob);
+            for line in lines {
+                assert_eq!(matcher.is_match(line.as_bytes()), glob.matches_partially(line),
+                           "pattern {:?} on line {:?}", pattern, line);
+            }
+        }
+    }
+
+    #[test]
+    fn glob_to_regex_translates_classes_and_escapes() {
+        assert_eq!(glob_to_regex("a.b"), r"a\.b");
+        assert_eq!(glob_to_regex("[a-c]"), "[a-c]");
+        assert_eq!(glob_to_regex("[]a]"), r"[\]a]");
+        assert_eq!(glob_to_regex("[!a]"), format!("(?:[^a]|{})", INVALID_BYTE));
+        assert_eq!(glob_to_regex(r"\*"), r"\*");
+        // unterminated classes and trailing backslashes are taken literally
+        assert_eq!(glob_to_regex("[a"), r"\[a");
+        assert_eq!(glob_to_regex("a\\"), r"a\\");
+    }
+
+    #[test]
+    fn word_matcher_only_accepts_whole_words() {
+        assert_eq!(word_matcher("foo").find_at(b"foo", 0), Some((0, 3)));
+        assert_eq!(word_matcher("foo").find_at(b"foobar", 0), None);
+        assert_eq!(word_matcher("foo").find_at(b"a_foo", 0), None);
+        // a rejected occurrence does not hide a later one
+        assert_eq!(word_matcher("foo").find_at(b"foobar foo", 0), Some((7, 10)));
+        assert_eq!(word_matcher("foo").find_at("(foo)".as_bytes(), 0), Some((1, 4)));
+        // non-ASCII letters are word characters, invalid bytes are not
+        assert_eq!(word_matcher("foo").find_at("éfoo".as_bytes(), 0), None);
+        assert_eq!(word_matcher("foo").find_at(b"\xE9foo", 0), Some((1, 4)));
+    }
+
+    #[test]
+    fn wildcards_match_latin1_bytes() {
+        let line = b"caf\xE9 and foo\xE9bar";
+        assert_eq!(matcher("caf?", PatternSyntax::Glob).find_at(line, 0), Some((0, 4)));
+        assert_eq!(matcher("caf[!a]", PatternSyntax::Glob).find_at(line, 0), Some((0, 4)));
+        assert!(matcher("foo*bar", PatternSyntax::Glob).is_match(line));
+        assert_eq!(matcher("caf.", PatternSyntax::ExtendedRegex).find_at(line, 0), Some((0, 4)));
+        assert_eq!(matcher("caf[^a]", PatternSyntax::ExtendedRegex).find_at(line, 0), Some((0, 4)));
+        assert!(matcher("foo.*bar", PatternSyntax::ExtendedRegex).is_match(line));
+    }
+
+    #[test]
+    fn wildcards_match_whole_utf8_characters() {
+        let line = "café".as_bytes();
+        assert_eq!(matcher("caf?", PatternSyntax::Glob).find_at(line, 0), Some((0, 5)));
+        assert_eq!(matcher("caf.", PatternSyntax::ExtendedRegex).find_at(line, 0), Some((0, 5)));
+    }
+
+    #[test]
+    fn only_unescaped_dots_outside_classes_are_rewritten() {
+        assert_eq!(match_invalid_utf8(r"a\.b"), r"a\.b");
+        assert_eq!(match_invalid_utf8("[.]"), "[.]");
+        assert_eq!(match_invalid_utf8("[[:alpha:].]x"), "[[:alpha:].]x");
+        assert_eq!(match_invalid_utf8("a.b"), format!("a{}b", ANY_CHAR));
+        assert_eq!(match_invalid_utf8("[^]a]"), format!("(?:[^]a]|{})", INVALID_BYTE));
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    Include,
+    Exclude,
+    ExcludeDir,
+}
+
+// decides which files and directories are searched, based on --include, --exclude and
+// --exclude-dir globs. Globs must match the whole file name (or the whole path).
+pub struct PathFilter {
+    // in the order they were given on the command line
+    rules: Vec<(FilterKind, glob::ParsedGlobString)>,
+}
+
+impl PathFilter {
+    pub fn new() -> Self {
+        PathFilter { rules: Vec::new() }
+    }
+
+    pub fn add(&mut self, kind: FilterKind, glob: &str) -> Result<(), String> {
+        match glob::ParsedGlobString::try_from(glob) {
+            Ok(glob) => {
+                self.rules.push((kind, glob));
+                Ok(())
+            }
+            Err(error) => Err(format!("{:?}", error)),
+        }
+    }
+
+    // Like in GNU grep, the last matching --include or --exclude decides. If none of them
+    // matches, the file is searched unless the first of them is an --include.
+    pub fn includes_file(&self, path: &str) -> bool {
+        let included_by_default = self.file_rules().next().is_none_or(|(kind, _glob)| *kind != FilterKind::Include);
+        self.last_matching_file_rule(path).map_or(included_by_default, |kind| kind == FilterKind::Include)
+    }
+
+    // whether an --exclude decides against the file, i.e. like `!includes_file`, but without
+    // excluding unmatched files if the first rule is an --include
+    pub fn excludes_file(&self, path: &str) -> bool {
+        self.last_matching_file_rule(path) == Some(FilterKind::Exclude)
+    }
+
+    fn file_rules(&self) -> impl DoubleEndedIterator<Item = &(FilterKind, glob::ParsedGlobString)> {
+        self.rules.iter().filter(|(kind, _glob)| *kind != FilterKind::ExcludeDir)
+    }
+
+    fn last_matching_file_rule(&self, path: &str) -> Option<FilterKind> {
+        self.file_rules().rev()
+            .find(|(_kind, glob)| matches(glob, path))
+            .map(|(kind, _glob)| *kind)
+    }
+
+    pub fn includes_dir(&self, path: &str) -> bool {
+        !self.rules.iter().any(|(kind, glob)| *kind == FilterKind::ExcludeDir && matches(glob, path))
+    }
+}
+
+// globs may either match the file name or the whole path, which is taken without a leading
+// "./", so that e.g. "src/*.rs" also matches when searching the current directory
+fn matches(glob: &glob::ParsedGlobString, path: &str) -> bool {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy());
+    file_name.is_some_and(|name| glob.matches(&name)) || glob.matches(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_filter(rules: &[(FilterKind, &str)]) -> PathFilter {
+        let mut filter = PathFilter::new();
+        for (kind, glob) in rules {
+            filter.add(*kind, glob).unwrap();
+        }
+        filter
+    }
+
+    #[test]
+    fn everything_is_included_without_rules() {
+        assert!(PathFilter::new().includes_file("a/b.txt"));
+        assert!(PathFilter::new().includes_dir("a"));
+    }
+
+    #[test]
+    fn includes_file_uses_the_last_matching_rule() {
+        let filter = new_filter(&[(FilterKind::Exclude, "*.txt"), (FilterKind::Include, "keep.txt")]);
+        assert!(filter.includes_file("dir/keep.txt"));
+        assert!(!filter.includes_file("dir/other.txt"));
+        assert!(filter.includes_file("dir/other.rs"));
+        let filter = new_filter(&[(FilterKind::Include, "keep.txt"), (FilterKind::Exclude, "*.txt")]);
+        assert!(!filter.includes_file("keep.txt"));
+    }
+
+    #[test]
+    fn unmatched_files_are_excluded_if_the_first_rule_is_an_include() {
+        let filter = new_filter(&[(FilterKind::Include, "*.rs"), (FilterKind::Exclude, "main.rs")]);
+        assert!(filter.includes_file("src/lib.rs"));
+        assert!(!filter.includes_file("src/main.rs"));
+        assert!(!filter.includes_file("README.md"));
+    }
+
+    #[test]
+    fn excludes_file_ignores_the_default() {
+        let filter = new_filter(&[(FilterKind::Include, "*.txt"), (FilterKind::Exclude, "vendor.tar.gz")]);
+        assert!(!filter.excludes_file("release.tar.gz"));
+        assert!(filter.excludes_file("vendor.tar.gz"));
+        assert!(!filter.excludes_file("notes.txt"));
+    }
+
+    #[test]
+    fn globs_match_the_file_name_or_the_whole_path() {
+        let filter = new_filter(&[(FilterKind::Exclude, "src/*.rs")]);
+        assert!(!filter.includes_file("src/main.rs"));
+        assert!(filter.includes_file("main.rs"));
+    }
+
+    #[test]
+    fn whole_path_globs_ignore_a_leading_current_dir() {
+        let filter = new_filter(&[(FilterKind::Exclude, "src/*.rs"), (FilterKind::ExcludeDir, "src/gen")]);
+        assert!(!filter.includes_file("./src/main.rs"));
+        assert!(!filter.includes_dir("./src/gen"));
+        assert!(filter.includes_dir("./src"));
+    }
+
+    #[test]
+    fn directories_are_only_affected_by_exclude_dir() {
+        let filter = new_filter(&[(FilterKind::Include, "*.rs"), (FilterKind::ExcludeDir, "target")]);
+        assert!(filter.includes_dir("src"));
+        assert!(!filter.includes_dir("target"));
+        assert!(!filter.includes_dir("sub/target"));
+        assert!(filter.includes_file("target.rs"));
+    }
+}
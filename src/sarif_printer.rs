@@ -0,0 +1,181 @@
+use std::io;
+use std::path::Path;
+
+use serde_json::json;
+use serde_json::Value;
+
+use crate::hit_handling;
+use crate::hit_handling::HitHandler;
+use crate::matching::Matcher;
+
+// For --sarif: collects the hits as results of a SARIF 2.1.0 log, which is printed after all
+// files have been searched. Each pattern is a rule, identified by its position ("e1" for the
+// first pattern), since patterns may be empty, repeated or contain characters that are not valid
+// in rule ids. Each hit is a result of every rule whose pattern matches the line. Hits selected by
+// --invert-match would not match any rule, which is why --sarif conflicts with it.
+pub struct SarifPrinter {
+    // the patterns, with a matcher for each of them alone
+    rules: Vec<(String, Box<dyn Matcher>)>,
+    results: Vec<Value>,
+}
+
+impl SarifPrinter {
+    pub fn new(rules: Vec<(String, Box<dyn Matcher>)>) -> Self {
+        SarifPrinter { rules, results: Vec::new() }
+    }
+
+    pub fn print_log(&self) -> io::Result<()> {
+        let rules: Vec<Value> = self.rules.iter()
+            .enumerate()
+            .map(|(rule_index, (pattern, _matcher))| json!({
+                "id": rule_id(rule_index),
+                "shortDescription": { "text": format!("lines matching {}", pattern) },
+                "properties": { "pattern": pattern },
+            }))
+            .collect();
+        let log = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "columnKind": "unicodeCodePoints",
+                "results": self.results,
+            }],
+        });
+        let mut output = serde_json::to_vec_pretty(&log).expect("failed serializing SARIF");
+        output.push(b'\n');
+        hit_handling::write_to_stdout(&output)
+    }
+
+    fn add_result(&mut self, rule_index: usize, file_path: &str, line: usize, hit: &[u8], span: (usize, usize)) {
+        let (start, end) = span;
+        let text = String::from_utf8_lossy(hit);
+        let region = json!({
+            "startLine": line,
+            "startColumn": column(hit, start),
+            "endColumn": column(hit, end),
+            "snippet": { "text": text },
+        });
+        let location = json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": file_uri(file_path) },
+                "region": region,
+            },
+        });
+        let pattern = &self.rules[rule_index].0;
+        self.results.push(json!({
+            "ruleId": rule_id(rule_index),
+            "ruleIndex": rule_index,
+            "message": { "text": format!("line matches {}", pattern) },
+            "locations": [location],
+        }));
+    }
+}
+
+impl HitHandler for SarifPrinter {
+    #[allow(unused_variables)]
+    fn start_new_file(&mut self, file_path: &str) -> io::Result<()> { Ok(()) }
+    #[allow(unused_variables)]
+    fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+        // the region is that of the first match of the rule's pattern
+        let rule_matches: Vec<(usize, (usize, usize))> = self.rules.iter().enumerate()
+            .filter_map(|(rule_index, (_pattern, matcher))| Some((rule_index, matcher.find_at(hit, 0)?)))
+            .collect();
+        for (rule_index, span) in rule_matches {
+            self.add_result(rule_index, file_path, line, hit, span);
+        }
+        Ok(())
+    }
+    #[allow(unused_variables)]
+    fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> { Ok(()) }
+}
+
+fn rule_id(rule_index: usize) -> String {
+    format!("e{}", rule_index + 1)
+}
+
+// the one-based column of the given byte offset, counted in code points
+fn column(line: &[u8], offset: usize) -> usize {
+    String::from_utf8_lossy(&line[..offset]).chars().count() + 1
+}
+
+// Relative paths become relative URI references, which SARIF consumers resolve against the
+// root of the repository. Everything but unreserved characters and "/" is percent-encoded,
+// including the ":" of archive members, which could otherwise be taken for a URI scheme.
+fn file_uri(path: &str) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let mut uri = match Path::new(path).is_absolute() {
+        true => String::from("file://"),
+        false => String::new(),
+    };
+    for byte in path.replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching;
+    use crate::matching::MatcherOptions;
+    use crate::matching::PatternSyntax;
+
+    fn printer(patterns: &[&str]) -> SarifPrinter {
+        let options = MatcherOptions {
+            syntax: PatternSyntax::FixedString,
+            case_insensitive: false,
+            whole_words: false,
+            whole_lines: false,
+        };
+        let rules = patterns.iter()
+            .map(|pattern| (pattern.to_string(), matching::new_matcher(pattern, &options).unwrap()))
+            .collect();
+        SarifPrinter::new(rules)
+    }
+
+    #[test]
+    fn hits_are_results_of_every_matching_rule() {
+        let mut printer = printer(&["foo", "bar", "baz"]);
+        printer.handle_hit("a.txt", 3, 0, b"bar foo", true, &[(0, 3), (4, 7)]).unwrap();
+        assert_eq!(printer.results.len(), 2);
+        assert_eq!(printer.results[0]["ruleId"], "e1");
+        assert_eq!(printer.results[0]["ruleIndex"], 0);
+        assert_eq!(printer.results[1]["ruleId"], "e2");
+        let region = &printer.results[0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 3);
+        assert_eq!(region["startColumn"], 5);
+        assert_eq!(region["endColumn"], 8);
+    }
+
+    #[test]
+    fn rule_ids_do_not_depend_on_the_pattern() {
+        assert_eq!(rule_id(0), "e1");
+        assert_eq!(rule_id(9), "e10");
+    }
+
+    #[test]
+    fn columns_are_counted_in_code_points() {
+        let line = "héllo wörld".as_bytes();
+        assert_eq!(column(line, 0), 1);
+        assert_eq!(column(line, 3), 3);
+        assert_eq!(column(line, line.len()), 12);
+    }
+
+    #[test]
+    fn file_uris_are_percent_encoded() {
+        assert_eq!(file_uri("./src/main.rs"), "src/main.rs");
+        assert_eq!(file_uri("a b/c%d.rs"), "a%20b/c%25d.rs");
+        assert_eq!(file_uri("x.tar.gz:dir/y.txt"), "x.tar.gz%3Adir/y.txt");
+        assert_eq!(file_uri("/tmp/é"), "file:///tmp/%C3%A9");
+    }
+}
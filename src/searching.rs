@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::io::Read;
+
+use encoding_rs::Encoding;
+
+use crate::errors::Error;
+use crate::hit_handling;
+use crate::hit_handling::HitHandler;
+use crate::input;
+use crate::input::BinaryFiles;
+use crate::matching::Matcher;
+
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    pub invert_match: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub binary_files: BinaryFiles,
+    // only report whether binary files match, instead of passing their lines to the hit handlers
+    pub summarize_binary_files: bool,
+    pub skip_file_after_first_match: bool,
+    // stop reading the input after this many selected lines (but still print their trailing context)
+    pub max_count: Option<usize>,
+    pub search_zip: bool,
+    pub encoding: Option<&'static Encoding>,
+}
+
+// Searches a single input (a file, standard input or an archive member), given as its raw
+// bytes, and passes the hits and context lines to the hit handlers. Returns the number of
+// selected lines (a matching binary file counts as one). Errors reading the input are returned
+// as `Error::Io`, errors writing the output as `Error::Output`.
+pub fn search<'r>(source: Box<dyn Read + 'r>, file_path: &str, matcher: &dyn Matcher, options: &SearchOptions,
+                  hit_handlers: &mut [&mut dyn HitHandler]) -> Result<usize, Error> {
+    let source = match options.search_zip {
+        true => input::decompressing_reader(source).map_err(|error| Error::io(file_path, error))?,
+        false => source,
+    };
+    let mut reader = input::decoding_reader(source, options.encoding);
+
+    for hit_handler in hit_handlers.iter_mut() {
+        hit_handler.start_new_file(file_path).map_err(Error::Output)?;
+    }
+
+    if options.max_count == Some(0) {
+        finish_file(hit_handlers, file_path, 0)?;
+        return Ok(0)
+    }
+
+    // errors are not handled here, since they will occur again when reading the lines
+    let binary = options.binary_files != BinaryFiles::Text && input::is_binary(&mut *reader).unwrap_or(false);
+    if binary && options.binary_files == BinaryFiles::WithoutMatch {
+        finish_file(hit_handlers, file_path, 0)?;
+        return Ok(0)
+    }
+    // the lines of binary files are not printed, just whether the file matches
+    let summarize_binary = binary && options.summarize_binary_files;
+
+    // non-matching lines that may still be printed as leading context of a later hit
+    let mut before_lines: VecDeque<(usize, usize, Vec<u8>)> = VecDeque::with_capacity(options.before_context);
+    // number of lines after the last hit that are still to be printed as trailing context
+    let mut remaining_after_lines = 0;
+    let mut selected_lines = 0;
+    // byte offset of the current line, counting the line terminators
+    let mut offset = 0;
+
+    for (line_no, line) in input::byte_lines(reader).enumerate() {
+        let input::Line { bytes: line, terminated } = match line {
+            Ok(line) => line,
+            Err(error) => {
+                // the lines read so far have been handled, so the file is still finished
+                finish_file(hit_handlers, file_path, offset)?;
+                return Err(Error::io(file_path, error))
+            }
+        };
+        let line_offset = offset;
+        offset += line.len() + terminated as usize;
+        let mut matches = matcher.is_match(&line);
+        if options.invert_match {
+            matches = !matches;
+        }
+        // once the maximum is reached, only the trailing context up to the next selected line is printed
+        let max_reached = options.max_count.is_some_and(|max_count| selected_lines >= max_count);
+        if max_reached && (matches || remaining_after_lines == 0) {
+            break
+        }
+        if matches && summarize_binary {
+            hit_handling::write_to_stdout(format!("Binary file {} matches\n", file_path).as_bytes())
+                .map_err(Error::Output)?;
+            selected_lines += 1;
+            break
+        }
+        if matches {
+            selected_lines += 1;
+            // lines selected by --invert-match do not contain any matches
+            let spans = match options.invert_match {
+                true => Vec::new(),
+                false => matcher.find_iter(&line),
+            };
+            for (context_line_no, context_offset, context) in before_lines.drain(..) {
+                let spans = context_spans(matcher, &context, options.invert_match);
+                for hit_handler in hit_handlers.iter_mut() {
+                    // only the last line can lack a terminator, which is never leading context
+                    hit_handler.handle_context(file_path, context_line_no, context_offset, &context, true, &spans)
+                        .map_err(Error::Output)?;
+                }
+            }
+            for hit_handler in hit_handlers.iter_mut() {
+                hit_handler.handle_hit(file_path, line_no + 1, line_offset, &line, terminated, &spans).map_err(Error::Output)?;
+            }
+            if options.skip_file_after_first_match {
+                break
+            }
+            remaining_after_lines = options.after_context;
+            // avoids reading beyond the last selected line, if there is no trailing context
+            if options.max_count.is_some_and(|max_count| selected_lines >= max_count) && remaining_after_lines == 0 {
+                break
+            }
+        } else if remaining_after_lines > 0 {
+            let spans = context_spans(matcher, &line, options.invert_match);
+            for hit_handler in hit_handlers.iter_mut() {
+                hit_handler.handle_context(file_path, line_no + 1, line_offset, &line, terminated, &spans).map_err(Error::Output)?;
+            }
+            remaining_after_lines -= 1;
+        } else if options.before_context > 0 {
+            if before_lines.len() == options.before_context {
+                before_lines.pop_front();
+            }
+            before_lines.push_back((line_no + 1, line_offset, line));
+        }
+    }
+    finish_file(hit_handlers, file_path, offset)?;
+    Ok(selected_lines)
+}
+
+fn finish_file(hit_handlers: &mut [&mut dyn HitHandler], file_path: &str, bytes_searched: usize) -> Result<(), Error> {
+    for hit_handler in hit_handlers.iter_mut() {
+        hit_handler.finish_file(file_path, bytes_searched).map_err(Error::Output)?;
+    }
+    Ok(())
+}
+
+// context lines only contain matches if the selection is inverted
+fn context_spans(matcher: &dyn Matcher, line: &[u8], invert_match: bool) -> Vec<(usize, usize)> {
+    match invert_match {
+        true => matcher.find_iter(line),
+        false => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::matching;
+    use crate::matching::MatcherOptions;
+    use crate::matching::PatternSyntax;
+
+    // records the lines passed to it as "LINE:TEXT" for hits and "LINE-TEXT" for context lines
+    #[derive(Default)]
+    struct Recorder {
+        lines: Vec<String>,
+    }
+
+    impl HitHandler for Recorder {
+        #[allow(unused_variables)]
+        fn start_new_file(&mut self, file_path: &str) -> io::Result<()> { Ok(()) }
+        #[allow(unused_variables)]
+        fn handle_hit(&mut self, file_path: &str, line: usize, offset: usize, hit: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+            self.lines.push(format!("{}:{}", line, String::from_utf8_lossy(hit)));
+            Ok(())
+        }
+        #[allow(unused_variables)]
+        fn handle_context(&mut self, file_path: &str, line: usize, offset: usize, context: &[u8], terminated: bool, matches: &[(usize, usize)]) -> io::Result<()> {
+            self.lines.push(format!("{}-{}", line, String::from_utf8_lossy(context)));
+            Ok(())
+        }
+    }
+
+    fn options() -> SearchOptions {
+        SearchOptions {
+            invert_match: false,
+            before_context: 0,
+            after_context: 0,
+            binary_files: BinaryFiles::Binary,
+            summarize_binary_files: true,
+            skip_file_after_first_match: false,
+            max_count: None,
+            search_zip: false,
+            encoding: None,
+        }
+    }
+
+    // returns the recorded lines and the number of selected lines
+    fn search_for(pattern: &str, input: &str, options: &SearchOptions) -> (Vec<String>, usize) {
+        let matcher_options = MatcherOptions {
+            syntax: PatternSyntax::FixedString,
+            case_insensitive: false,
+            whole_words: false,
+            whole_lines: false,
+        };
+        let matcher = matching::new_matcher(pattern, &matcher_options).unwrap();
+        let mut recorder = Recorder::default();
+        let selected_lines = match search(Box::new(input.as_bytes()), "test", &*matcher, options, &mut [&mut recorder]) {
+            Ok(selected_lines) => selected_lines,
+            Err(error) => panic!("search failed: {}", error),
+        };
+        (recorder.lines, selected_lines)
+    }
+
+    #[test]
+    fn overlapping_context_is_passed_once() {
+        let options = SearchOptions { before_context: 2, after_context: 2, ..options() };
+        let (lines, selected_lines) = search_for("foo", "a\nfoo\nb\nfoo\nc\nd\ne", &options);
+        assert_eq!(lines, ["1-a", "2:foo", "3-b", "4:foo", "5-c", "6-d"]);
+        assert_eq!(selected_lines, 2);
+    }
+
+    #[test]
+    fn leading_context_is_limited_to_the_lines_after_the_trailing_context() {
+        let options = SearchOptions { before_context: 1, after_context: 1, ..options() };
+        let (lines, _) = search_for("foo", "foo\na\nb\nc\nfoo", &options);
+        assert_eq!(lines, ["1:foo", "2-a", "4-c", "5:foo"]);
+    }
+
+    #[test]
+    fn context_lines_are_the_matching_ones_with_invert_match() {
+        let options = SearchOptions { invert_match: true, after_context: 1, ..options() };
+        let (lines, selected_lines) = search_for("foo", "a\nfoo\nfoo", &options);
+        assert_eq!(lines, ["1:a", "2-foo"]);
+        assert_eq!(selected_lines, 1);
+    }
+
+    #[test]
+    fn max_count_still_passes_the_trailing_context_up_to_the_next_hit() {
+        let options = SearchOptions { max_count: Some(1), after_context: 2, ..options() };
+        let (lines, selected_lines) = search_for("foo", "foo\na\nfoo\nb", &options);
+        assert_eq!(lines, ["1:foo", "2-a"]);
+        assert_eq!(selected_lines, 1);
+    }
+
+    #[test]
+    fn max_count_counts_non_matching_lines_with_invert_match() {
+        let options = SearchOptions { max_count: Some(2), invert_match: true, ..options() };
+        let (lines, selected_lines) = search_for("foo", "foo\na\nfoo\nb\nc", &options);
+        assert_eq!(lines, ["2:a", "4:b"]);
+        assert_eq!(selected_lines, 2);
+    }
+
+    #[test]
+    fn max_count_zero_selects_nothing() {
+        let options = SearchOptions { max_count: Some(0), ..options() };
+        let (lines, selected_lines) = search_for("foo", "foo", &options);
+        assert!(lines.is_empty());
+        assert_eq!(selected_lines, 0);
+    }
+}
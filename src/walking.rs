@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::archives::ArchiveKind;
+use crate::errors::Error;
+use crate::ignore_files;
+use crate::ignore_files::IgnoreFile;
+use crate::path_filter::PathFilter;
+
+// names of the ignore files that are read in every directory, in increasing order of precedence
+const VCS_IGNORE_FILE_NAMES : [&str; 1] = [".gitignore"];
+const IGNORE_FILE_NAMES : [&str; 2] = [".ignore", ".grepignore"];
+
+pub struct WalkOptions {
+    pub follow_symlinks: bool,
+    // also search files and directories whose names start with a "."
+    pub hidden: bool,
+    // respect .ignore and .grepignore files
+    pub ignore_files: bool,
+    // respect .gitignore files, .git/info/exclude and the global gitignore (only within a
+    // git repository)
+    pub ignore_vcs: bool,
+    // --include, --exclude and --exclude-dir
+    pub path_filter: PathFilter,
+    // --search-archives
+    pub search_archives: bool,
+}
+
+impl WalkOptions {
+    // Archives that are searched are only skipped if an --exclude (or --type-not) matches them,
+    // not because they do not match an --include, since the --include applies to their members:
+    // --include='*.txt' finds the text files in a .tar.gz.
+    pub fn includes_file(&self, path: &str) -> bool {
+        match self.search_archives && ArchiveKind::from_path(path).is_some() {
+            true => !self.path_filter.excludes_file(path),
+            false => self.path_filter.includes_file(path),
+        }
+    }
+}
+
+// Expands the directories among the given paths into the files they contain (recursively),
+// lazily, so that the search can stop before the walk is complete. The order is
+// deterministic: the entries of each directory are visited sorted by name. Symbolic links
+// given as paths are always followed, those found while walking only if `follow_symlinks` is
+// set. Paths given explicitly are never subject to the ignore rules, but to the path filter
+// (see `WalkOptions::includes_file`). Directories that cannot be read are skipped, and
+// yielded as errors among the files. The paths are yielded as they are, even if they are not
+// valid UTF-8, and only converted for matching them against the path filter.
+pub fn walk<'o>(paths: &'o [String], options: &'o WalkOptions) -> Walker<'o> {
+    Walker {
+        options,
+        paths: paths.iter(),
+        visited_dirs: HashSet::new(),
+        in_git_repo: false,
+        ignore_files: Vec::new(),
+        dirs: Vec::new(),
+    }
+}
+
+pub struct Walker<'o> {
+    options: &'o WalkOptions,
+    // the paths given that have not been visited yet
+    paths: std::slice::Iter<'o, String>,
+    // canonical paths of the directories walked so far, so that symlink cycles terminate
+    visited_dirs: HashSet<PathBuf>,
+    in_git_repo: bool,
+    // the ignore files applying to the current directory, in increasing order of precedence
+    ignore_files: Vec<IgnoreFile>,
+    // the directories currently being walked, the innermost one last
+    dirs: Vec<OpenDir>,
+}
+
+struct OpenDir {
+    // the entries that have not been visited yet
+    entries: std::vec::IntoIter<fs::DirEntry>,
+    // the path the ignore rules are matched against
+    absolute_path: PathBuf,
+    // the number of ignore files to pop when leaving the directory
+    loaded_ignore_files: usize,
+}
+
+impl<'o> Iterator for Walker<'o> {
+    type Item = Result<PathBuf, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dir = match self.dirs.last_mut() {
+                Some(dir) => dir,
+                None => {
+                    let path = self.paths.next()?;
+                    match self.visit_path(path) {
+                        Some(file) => return Some(file),
+                        None => continue,
+                    }
+                }
+            };
+            match dir.entries.next() {
+                Some(entry) => {
+                    let absolute_path = dir.absolute_path.join(entry.file_name());
+                    if let Some(file) = self.visit_entry(entry, absolute_path) {
+                        return Some(file);
+                    }
+                }
+                None => {
+                    let dir = self.dirs.pop().unwrap();
+                    self.ignore_files.truncate(self.ignore_files.len() - dir.loaded_ignore_files);
+                }
+            }
+        }
+    }
+}
+
+impl<'o> Walker<'o> {
+    // returns the file (or error) to yield, if any
+    fn visit_path(&mut self, path: &str) -> Option<Result<PathBuf, Error>> {
+        // "-" is standard input, even if there happens to be a directory of that name
+        if path == "-" {
+            Some(Ok(PathBuf::from(path)))
+        } else if Path::new(path).is_dir() {
+            // ".", ".." etc. have no name of their own, so that e.g. --exclude-dir='.*' only
+            // skips the hidden directories within them, like in GNU grep
+            let has_name = Path::new(path).file_name().is_some();
+            match !has_name || self.options.path_filter.includes_dir(path) {
+                true => self.open_root(Path::new(path)).err().map(|error| Err(Error::io(path, error))),
+                false => None,
+            }
+        } else if self.options.includes_file(path) {
+            Some(Ok(PathBuf::from(path)))
+        } else {
+            None
+        }
+    }
+
+    fn open_root(&mut self, root: &Path) -> io::Result<()> {
+        let absolute_root = fs::canonicalize(root)?;
+        self.ignore_files.clear();
+        let repo_root = absolute_root.ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf);
+        self.in_git_repo = repo_root.is_some();
+
+        if let Some(repo_root) = &repo_root {
+            if self.options.ignore_files && self.options.ignore_vcs {
+                let global_gitignore = ignore_files::global_gitignore_path()
+                    .and_then(|path| IgnoreFile::load(&path, repo_root));
+                self.ignore_files.extend(global_gitignore);
+                self.ignore_files.extend(IgnoreFile::load(&repo_root.join(".git/info/exclude"), repo_root));
+            }
+        }
+
+        // the ignore files of parent directories apply as well, up to the repository root
+        let top = repo_root.as_deref().unwrap_or(&absolute_root);
+        let mut parents: Vec<&Path> = absolute_root.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(top))
+            .collect();
+        parents.reverse();
+        for parent in parents {
+            let ignore_files = self.load_ignore_files(parent);
+            self.ignore_files.extend(ignore_files);
+        }
+
+        self.open_dir(root, &absolute_root)
+    }
+
+    // `dir` is the path as it will be printed, `absolute_dir` the one the ignore rules are
+    // matched against
+    fn open_dir(&mut self, dir: &Path, absolute_dir: &Path) -> io::Result<()> {
+        if !self.visited_dirs.insert(fs::canonicalize(dir)?) {
+            return Ok(());
+        }
+        // read before the ignore files are pushed, so that they are not left behind on errors
+        let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let ignore_files = self.load_ignore_files(absolute_dir);
+        let loaded_ignore_files = ignore_files.len();
+        self.ignore_files.extend(ignore_files);
+        self.dirs.push(OpenDir {
+            entries: entries.into_iter(),
+            absolute_path: absolute_dir.to_path_buf(),
+            loaded_ignore_files,
+        });
+        Ok(())
+    }
+
+    // returns the file (or error) to yield, if any
+    fn visit_entry(&mut self, entry: fs::DirEntry, absolute_path: PathBuf) -> Option<Result<PathBuf, Error>> {
+        if !self.options.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            return None;
+        }
+        let path = entry.path();
+        let mut file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(error) => return Some(Err(Error::io(&path.to_string_lossy(), error))),
+        };
+        if file_type.is_symlink() {
+            if !self.options.follow_symlinks {
+                return None;
+            }
+            file_type = match fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                // dangling symlink
+                Err(_) => return None,
+            };
+        }
+        if self.is_ignored(&absolute_path, file_type.is_dir()) {
+            return None;
+        }
+        let path_string = path.to_string_lossy();
+        if file_type.is_dir() {
+            match self.options.path_filter.includes_dir(&path_string) {
+                true => self.open_dir(&path, &absolute_path).err().map(|error| Err(Error::io(&path_string, error))),
+                false => None,
+            }
+        } else if file_type.is_file() && self.options.includes_file(&path_string) {
+            Some(Ok(path))
+        } else {
+            None
+        }
+    }
+
+    fn load_ignore_files(&self, dir: &Path) -> Vec<IgnoreFile> {
+        if !self.options.ignore_files {
+            return Vec::new();
+        }
+        let mut names: Vec<&str> = Vec::new();
+        if self.options.ignore_vcs && self.in_git_repo {
+            names.extend(VCS_IGNORE_FILE_NAMES);
+        }
+        names.extend(IGNORE_FILE_NAMES);
+        names.iter().filter_map(|name| IgnoreFile::load(&dir.join(name), dir)).collect()
+    }
+
+    // the last ignore file with a matching rule decides
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignore_files.iter().rev()
+            .find_map(|ignore_file| ignore_file.is_ignored(path, is_dir))
+            .unwrap_or(false)
+    }
+}